@@ -0,0 +1,90 @@
+use crate::configs::{migrate_level_file, LevelConfig};
+
+const USAGE: &str = "Usage:
+    parabox                        start the game
+    parabox migrate <file>...      rewrite level files in the current format
+    parabox convert <from> <to>    convert a level between JSON and ASCII (.txt)
+    parabox convert --original <from> <to>
+                                   write the original game's level format instead of ASCII;
+                                   .txt levels from the original game are recognised when read";
+
+/// Run a command-line tool if one was requested instead of the game.
+/// Returns `None` when the game should start, otherwise the process exit code.
+pub fn run(args: &[String]) -> Option<i32> {
+    let (command, files) = args.split_first()?;
+    match command.as_str() {
+        "migrate" => Some(migrate(files)),
+        "convert" => match files {
+            [from, to] => Some(convert(from, to, false)),
+            [flag, from, to] if flag == "--original" => Some(convert(from, to, true)),
+            _ => {
+                eprintln!("{}", USAGE);
+                Some(2)
+            }
+        },
+        _ => {
+            eprintln!("Unknown command: {}\n{}", command, USAGE);
+            Some(2)
+        }
+    }
+}
+
+fn migrate(files: &[String]) -> i32 {
+    let mut code = 0;
+    for file in files {
+        match migrate_level_file(file) {
+            Ok(true) => println!("{}: migrated", file),
+            Ok(false) => println!("{}: already up to date", file),
+            Err(error) => {
+                eprintln!("{}", error);
+                code = 1;
+            }
+        }
+    }
+    code
+}
+
+fn is_ascii_file(file: &str) -> bool {
+    file.ends_with(".txt")
+}
+
+// Text files saved by the original game open with their format version.
+fn read_text_level(file: &str) -> Result<LevelConfig, String> {
+    let text = std::fs::read_to_string(file).map_err(|error| format!("{}: {}", file, error))?;
+    let level = if text.starts_with("version ") {
+        LevelConfig::from_original(0, &text)
+    } else {
+        LevelConfig::from_ascii(0, &text)
+    };
+    level.map_err(|error| format!("{}: {}", file, error))
+}
+
+fn convert(from: &str, to: &str, original: bool) -> i32 {
+    let level = if is_ascii_file(from) {
+        read_text_level(from)
+    } else {
+        LevelConfig::try_new(0, from).map_err(|error| error.to_string())
+    };
+    let result = level.and_then(|level| {
+        if original {
+            std::fs::write(to, level.to_original()).map_err(|error| format!("{}: {}", to, error))
+        } else if is_ascii_file(to) {
+            let text = level
+                .to_ascii()
+                .ok_or_else(|| format!("{}: too many boxes to draw as ASCII", from))?;
+            std::fs::write(to, text).map_err(|error| format!("{}: {}", to, error))
+        } else {
+            level.save(to).map_err(|error| format!("{}: {}", to, error))
+        }
+    });
+    match result {
+        Ok(()) => {
+            println!("{} -> {}", from, to);
+            0
+        }
+        Err(error) => {
+            eprintln!("{}", error);
+            1
+        }
+    }
+}
@@ -0,0 +1,523 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use super::{LevelConfig, LevelLoadError, Side, Square, Target, WinCondition};
+use super::level_file::{Cell, CellContent, LevelFile, ParaboxFile, RulesFile, RulesPreset, TypedTarget, LEVEL_FORMAT_VERSION};
+
+// Digits name paraboxes by id + 1, so a text level holds at most nine of them
+const MAX_ASCII_BOXES: usize = 9;
+
+impl LevelConfig {
+    /// Parse a level drawn with the in-game symbols from the README:
+    ///
+    /// ```text
+    /// ; comment
+    /// box 1
+    /// #######
+    /// #=.2.b#
+    /// #..p._#
+    /// #######
+    ///
+    /// box 2 in 1
+    /// #.#
+    /// ..B
+    /// ###
+    /// target 1 1
+    /// ```
+    ///
+    /// Each `box <n>` header starts parabox `n` (numbered from 1, in order), optionally placed
+    /// `in` its outer box, and is followed by its rows. On top of the README symbols, `B` is a
+    /// block on a target and `P` the player on their target. Targets hidden under anything else
+    /// are listed after the rows as `target <x> <y>` or `player_target <x> <y>`, and so are
+    /// targets that need one particular square: `target <x> <y> block`, `target <x> <y> box <n>`
+    /// and `player_target <x> <y> <n>`, players being numbered from 1 in the order they are drawn.
+    ///
+    /// A box may be drawn more than once. The first copy in its `in` box is its way out and the
+    /// rest are clones, unless an `exit <x> <y>` line after its rows names another copy.
+    /// `infinity <x> <y>` marks the copy drawn at that cell as an infinity box, and
+    /// `epsilon_of <n>` makes the box the epsilon box of box `n`, `flipped` mirrors its
+    /// inside left to right, `player` makes the box the player in a level without `p`,
+    /// `player <n>` makes it player `n` and `player <x> <y> <n>` numbers the `p` at that cell,
+    /// for levels whose players are not numbered in the order they are drawn,
+    /// `locked` keeps the box from ever being pushed, and `entry <side> <i>` sets the cell
+    /// across that side which squares entering through it try first.
+    ///
+    /// Lines before the first box set up the whole level: `max_move_depth <n>`,
+    /// `rules <parabox|sokoban>` followed by any rules set on top of the preset as in
+    /// `rules sokoban max_push_chain 2 allow_pull true`, and one line for each win condition:
+    /// `win targets`, `win reach <n> <x> <y>`, `win inside <n> <outer>`, `win empty <n>` and
+    /// `win within <moves>`.
+    pub fn from_ascii(level: i32, text: &str) -> Result<Self, LevelLoadError> {
+        let mut paraboxes: Vec<ParaboxFile> = Vec::new();
+        let mut max_move_depth = None;
+        let mut rules = None;
+        let mut win_conditions = Vec::new();
+        let mut exits = HashMap::new();
+        // (box index, pos) of each `infinity` line, with the line for errors
+        let mut infinities = HashMap::new();
+        for (index, line) in text.lines().enumerate() {
+            let path = format!("line {}", index + 1);
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words[0] {
+                "max_move_depth" => {
+                    let [n] = words[1..] else {
+                        return Err(LevelLoadError::invalid(&path, "\"max_move_depth <n>\""));
+                    };
+                    let n = usize::try_from(read_number(n, &path)?).map_err(|_| LevelLoadError::invalid(&path, "a positive number"))?;
+                    max_move_depth = Some(n);
+                }
+                "rules" => rules = Some(read_rules(&words[1..], &path)?),
+                "win" => {
+                    let number = |word| read_number(word, &path);
+                    let condition = match words[1..] {
+                        ["targets"] => WinCondition::Targets,
+                        ["reach", n, x, y] => WinCondition::Reach { id: number(n)? - 1, pos: (number(x)?, number(y)?) },
+                        ["inside", n, outer] => WinCondition::Inside { id: number(n)? - 1, outer: number(outer)? - 1 },
+                        ["empty", n] => WinCondition::Empty { id: number(n)? - 1 },
+                        ["within", moves] => WinCondition::WithinMoves {
+                            moves: usize::try_from(number(moves)?).map_err(|_| LevelLoadError::invalid(&path, "a number of moves"))?,
+                        },
+                        _ => {
+                            let expected = "\"win targets\", \"win reach <n> <x> <y>\", \"win inside <n> <outer>\", \"win empty <n>\" or \"win within <moves>\"";
+                            return Err(LevelLoadError::invalid(&path, expected));
+                        }
+                    };
+                    win_conditions.push(condition);
+                }
+                "box" => {
+                    let expected = format!("\"box {}\" or \"box {} in <outer>\"", paraboxes.len() + 1, paraboxes.len() + 1);
+                    let outer = match words[1..] {
+                        [n] if n == (paraboxes.len() + 1).to_string() => None,
+                        [n, "in", outer] if n == (paraboxes.len() + 1).to_string() => {
+                            Some(read_number(outer, &path)? - 1)
+                        }
+                        _ => return Err(LevelLoadError::invalid(&path, expected)),
+                    };
+                    paraboxes.push(ParaboxFile { outer, ..ParaboxFile::default() });
+                }
+                keyword @ ("flipped" | "locked") => {
+                    let parabox = paraboxes.last_mut().ok_or_else(|| LevelLoadError::invalid(&path, "a \"box\" header first"))?;
+                    if words.len() > 1 {
+                        return Err(LevelLoadError::invalid(&path, format!("\"{}\" on its own", keyword)));
+                    }
+                    match keyword {
+                        "flipped" => parabox.flipped = true,
+                        _ => parabox.locked = true,
+                    }
+                }
+                "player" => {
+                    let parabox = paraboxes.last_mut().ok_or_else(|| LevelLoadError::invalid(&path, "a \"box\" header first"))?;
+                    let read_player_number = |n| {
+                        usize::try_from(read_number(n, &path)? - 1).map_err(|_| LevelLoadError::invalid(&path, "a player number from 1"))
+                    };
+                    match words[1..] {
+                        [] => parabox.player = true,
+                        [n] => {
+                            parabox.player = true;
+                            parabox.player_number = Some(read_player_number(n)?);
+                        }
+                        [x, y, n] => {
+                            let pos = (read_number(x, &path)?, read_number(y, &path)?);
+                            let cell = parabox.cells.iter_mut().find(|cell| cell.pos == pos && matches!(cell.content, CellContent::Player { .. }));
+                            let cell = cell.ok_or_else(|| LevelLoadError::invalid(&path, "the position of a player drawn in the box"))?;
+                            cell.content = CellContent::Player { number: Some(read_player_number(n)?) };
+                        }
+                        _ => return Err(LevelLoadError::invalid(&path, "\"player\", \"player <n>\" or \"player <x> <y> <n>\"")),
+                    }
+                }
+                "entry" => {
+                    let parabox = paraboxes.last_mut().ok_or_else(|| LevelLoadError::invalid(&path, "a \"box\" header first"))?;
+                    let side = match words[1..] {
+                        ["top", _] => Side::Top,
+                        ["bottom", _] => Side::Bottom,
+                        ["left", _] => Side::Left,
+                        ["right", _] => Side::Right,
+                        _ => return Err(LevelLoadError::invalid(&path, "\"entry <top|bottom|left|right> <i>\"")),
+                    };
+                    parabox.entry_points.insert(side, read_number(words[2], &path)?);
+                }
+                "epsilon_of" => {
+                    let parabox = paraboxes.last_mut().ok_or_else(|| LevelLoadError::invalid(&path, "a \"box\" header first"))?;
+                    let [n] = words[1..] else {
+                        return Err(LevelLoadError::invalid(&path, "\"epsilon_of <n>\""));
+                    };
+                    parabox.epsilon_of = Some(read_number(n, &path)? - 1);
+                }
+                keyword @ ("target" | "player_target" | "exit" | "infinity") => {
+                    let parabox = paraboxes.last_mut().ok_or_else(|| LevelLoadError::invalid(&path, "a \"box\" header first"))?;
+                    let expected = match keyword {
+                        "target" => "\"target <x> <y>\", optionally followed by \"block\" or \"box <n>\"".to_string(),
+                        "player_target" => "\"player_target <x> <y>\", optionally followed by <n>".to_string(),
+                        _ => format!("\"{} <x> <y>\"", keyword),
+                    };
+                    let [x, y, ref needs @ ..] = words[1..] else {
+                        return Err(LevelLoadError::invalid(&path, expected));
+                    };
+                    let pos = (read_number(x, &path)?, read_number(y, &path)?);
+                    let typed_target = |needs| TypedTarget { pos, needs };
+                    match (keyword, needs) {
+                        ("target", []) => parabox.targets.push(pos),
+                        ("target", ["block"]) => parabox.typed_targets.push(typed_target(Target::Block)),
+                        ("target", ["box", n]) => {
+                            parabox.typed_targets.push(typed_target(Target::Parabox { id: read_number(n, &path)? - 1 }));
+                        }
+                        ("player_target", []) => parabox.player_targets.push(pos),
+                        ("player_target", [n]) => {
+                            let number = usize::try_from(read_number(n, &path)? - 1)
+                                .map_err(|_| LevelLoadError::invalid(&path, "a player number from 1"))?;
+                            parabox.typed_targets.push(typed_target(Target::Player { number }));
+                        }
+                        ("exit", []) => {
+                            exits.insert(paraboxes.len() - 1, pos);
+                        }
+                        ("infinity", []) => {
+                            infinities.insert((paraboxes.len() - 1, pos), path);
+                        }
+                        _ => return Err(LevelLoadError::invalid(&path, expected)),
+                    }
+                }
+                _ => {
+                    let parabox = paraboxes.last_mut().ok_or_else(|| LevelLoadError::invalid(&path, "a \"box\" header first"))?;
+                    let x = parabox.size.0;
+                    let width = line.chars().count() as i32;
+                    if x > 0 && width != parabox.size.1 {
+                        return Err(LevelLoadError::invalid(&path, format!("a row {} symbols wide", parabox.size.1)));
+                    }
+                    for (y, glyph) in line.chars().enumerate() {
+                        let pos = (x, y as i32);
+                        match glyph {
+                            '.' | '_' | '=' => {}
+                            '#' => parabox.walls.push(pos),
+                            'b' | 'B' => parabox.cells.push(Cell { pos, content: CellContent::Block }),
+                            'p' | 'P' => parabox.cells.push(Cell { pos, content: CellContent::Player { number: None } }),
+                            '1'..='9' => {
+                                let id = glyph.to_digit(10).unwrap() as i32 - 1;
+                                parabox.cells.push(Cell { pos, content: CellContent::Parabox { id } });
+                            }
+                            _ => {
+                                return Err(LevelLoadError::invalid(
+                                    &format!("{}, column {}", path, y + 1),
+                                    format!("one of . # b B p P _ = 1-9, found {:?}", glyph),
+                                ));
+                            }
+                        }
+                        match glyph {
+                            '_' | 'B' => parabox.targets.push(pos),
+                            '=' | 'P' => parabox.player_targets.push(pos),
+                            _ => {}
+                        }
+                    }
+                    parabox.size = (x + 1, width);
+                }
+            }
+        }
+        if paraboxes.is_empty() {
+            return Err(LevelLoadError::invalid("line 1", "a \"box 1\" header"));
+        }
+        let outers: Vec<Option<i32>> = paraboxes.iter().map(|parabox| parabox.outer).collect();
+        let mut placed = vec![false; paraboxes.len()];
+        for (box_id, parabox) in paraboxes.iter_mut().enumerate() {
+            for cell in &mut parabox.cells {
+                if let CellContent::Parabox { id } = cell.content {
+                    if infinities.remove(&(box_id, cell.pos)).is_some() {
+                        cell.content = CellContent::Infinity { id };
+                        continue;
+                    }
+                    let index = id as usize;
+                    let is_exit = outers.get(index) == Some(&Some(box_id as i32))
+                        && !placed[index]
+                        && exits.get(&index).is_none_or(|&pos| pos == cell.pos);
+                    if is_exit {
+                        placed[index] = true;
+                    } else {
+                        cell.content = CellContent::Clone { id };
+                    }
+                }
+            }
+        }
+        if let Some(path) = infinities.into_values().min() {
+            return Err(LevelLoadError::invalid(&path, "the position of a box digit"));
+        }
+        LevelFile {
+            version: LEVEL_FORMAT_VERSION,
+            metadata: None,
+            max_move_depth,
+            rules,
+            win_conditions,
+            paraboxes,
+        }
+        .to_level_config(level)
+    }
+
+    /// Draw the level in the format read by `from_ascii`.
+    /// Returns `None` if the level has more paraboxes than digits can name.
+    pub fn to_ascii(&self) -> Option<String> {
+        if self.paraboxes.len() > MAX_ASCII_BOXES {
+            return None;
+        }
+        let mut out = String::new();
+        if let Some(max_move_depth) = self.max_move_depth {
+            writeln!(out, "max_move_depth {}", max_move_depth).unwrap();
+        }
+        if let Some(rules) = RulesFile::from_rules(self.rules) {
+            write!(out, "rules {}", if rules.preset == Some(RulesPreset::Sokoban) { "sokoban" } else { "parabox" }).unwrap();
+            let allowed = [
+                ("allow_enter", rules.allow_enter),
+                ("allow_exit", rules.allow_exit),
+                ("allow_eat", rules.allow_eat),
+                ("allow_pull", rules.allow_pull),
+            ];
+            for (name, allowed) in allowed {
+                if let Some(allowed) = allowed {
+                    write!(out, " {} {}", name, allowed).unwrap();
+                }
+            }
+            if let Some(max_push_chain) = rules.max_push_chain {
+                write!(out, " max_push_chain {}", max_push_chain).unwrap();
+            }
+            out.push('\n');
+        }
+        for condition in &self.win_conditions {
+            match *condition {
+                WinCondition::Targets => writeln!(out, "win targets"),
+                WinCondition::Reach { id, pos: (x, y) } => writeln!(out, "win reach {} {} {}", id + 1, x, y),
+                WinCondition::Inside { id, outer } => writeln!(out, "win inside {} {}", id + 1, outer + 1),
+                WinCondition::Empty { id } => writeln!(out, "win empty {}", id + 1),
+                WinCondition::WithinMoves { moves } => writeln!(out, "win within {}", moves),
+            }
+            .unwrap();
+        }
+        for parabox in &self.paraboxes {
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            match parabox.outer {
+                Some(outer) => writeln!(out, "box {} in {}", parabox.id + 1, outer + 1),
+                None => writeln!(out, "box {}", parabox.id + 1),
+            }
+            .unwrap();
+            write!(out, "{:?}", parabox).unwrap();
+            if let Some(outer) = parabox.outer
+                && self.paraboxes[outer as usize].map.values().any(|square| *square == Square::Clone(parabox.id))
+                && let Some((x, y)) = self.paraboxes[outer as usize].find_box(parabox.id)
+            {
+                writeln!(out, "exit {} {}", x, y).unwrap();
+            }
+            if let Some(epsilon_of) = parabox.epsilon_of {
+                writeln!(out, "epsilon_of {}", epsilon_of + 1).unwrap();
+            }
+            if parabox.flipped {
+                writeln!(out, "flipped").unwrap();
+            }
+            if parabox.player {
+                let number = self.paraboxes.iter()
+                    .find_map(|outer| Some((outer.id, outer.find_box(parabox.id)?)))
+                    .and_then(|location| self.player_number(location));
+                match number {
+                    Some(number) => writeln!(out, "player {}", number + 1).unwrap(),
+                    None => writeln!(out, "player").unwrap(),
+                }
+            }
+            let mut numbered: Vec<((i32, i32), usize)> = parabox.map.iter()
+                .filter(|(_, square)| **square == Square::Player)
+                .filter_map(|(&pos, _)| Some((pos, self.player_number((parabox.id, pos))?)))
+                .collect();
+            numbered.sort();
+            for ((x, y), number) in numbered {
+                writeln!(out, "player {} {} {}", x, y, number + 1).unwrap();
+            }
+            if parabox.locked {
+                writeln!(out, "locked").unwrap();
+            }
+            let mut entry_points: Vec<(&Side, &i32)> = parabox.entry_points.iter().collect();
+            entry_points.sort();
+            for (side, i) in entry_points {
+                writeln!(out, "entry {} {}", side, i).unwrap();
+            }
+            let mut infinities: Vec<(i32, i32)> = parabox.map.iter()
+                .filter(|(_, square)| matches!(square, Square::Infinity(_)))
+                .map(|(&pos, _)| pos)
+                .collect();
+            infinities.sort();
+            for (x, y) in infinities {
+                writeln!(out, "infinity {} {}", x, y).unwrap();
+            }
+            for &(x, y) in &parabox.targets {
+                if !matches!(parabox.glyph_at((x, y)).as_str(), "_" | "B") {
+                    writeln!(out, "target {} {}", x, y).unwrap();
+                }
+            }
+            for &(x, y) in &parabox.player_targets {
+                if !matches!(parabox.glyph_at((x, y)).as_str(), "=" | "P") {
+                    writeln!(out, "player_target {} {}", x, y).unwrap();
+                }
+            }
+            for &((x, y), target) in &parabox.typed_targets {
+                match target {
+                    Target::Block => writeln!(out, "target {} {} block", x, y),
+                    Target::Parabox { id } => writeln!(out, "target {} {} box {}", x, y, id + 1),
+                    Target::Player { number } => writeln!(out, "player_target {} {} {}", x, y, number + 1),
+                }
+                .unwrap();
+            }
+        }
+        Some(out)
+    }
+}
+
+// The rules of a `rules <preset> [<rule> <value>]...` line, given the words after `rules`.
+fn read_rules(words: &[&str], path: &str) -> Result<RulesFile, LevelLoadError> {
+    let expected = "\"rules <parabox|sokoban>\", then rules and their values such as \"allow_pull true\"";
+    let (preset, settings) = match words {
+        ["parabox", settings @ ..] => (RulesPreset::Parabox, settings),
+        ["sokoban", settings @ ..] => (RulesPreset::Sokoban, settings),
+        _ => return Err(LevelLoadError::invalid(path, expected)),
+    };
+    let mut rules = RulesFile { preset: Some(preset), ..RulesFile::default() };
+    for setting in settings.chunks(2) {
+        let allowed = |word: &str| word.parse().map_err(|_| LevelLoadError::invalid(path, format!("true or false, found {:?}", word)));
+        match *setting {
+            ["allow_enter", value] => rules.allow_enter = Some(allowed(value)?),
+            ["allow_exit", value] => rules.allow_exit = Some(allowed(value)?),
+            ["allow_eat", value] => rules.allow_eat = Some(allowed(value)?),
+            ["allow_pull", value] => rules.allow_pull = Some(allowed(value)?),
+            ["max_push_chain", value] => {
+                let limit = usize::try_from(read_number(value, path)?).map_err(|_| LevelLoadError::invalid(path, "a number of squares"))?;
+                rules.max_push_chain = Some(limit);
+            }
+            _ => return Err(LevelLoadError::invalid(path, expected)),
+        }
+    }
+    Ok(rules)
+}
+
+fn read_number(word: &str, path: &str) -> Result<i32, LevelLoadError> {
+    word.parse().map_err(|_| LevelLoadError::invalid(path, format!("a number, found {:?}", word)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shipped_levels_round_trip_through_ascii() {
+        for level in 1..=12 {
+            let file = format!("{}/assets/levels/{}.json", env!("CARGO_MANIFEST_DIR"), level);
+            let loaded = LevelConfig::try_new(level, &file).unwrap();
+            let ascii = loaded.to_ascii().unwrap();
+            let reloaded = LevelConfig::from_ascii(level, &ascii).unwrap();
+            assert_eq!(format!("{:?}", loaded), format!("{:?}", reloaded));
+            assert_eq!(ascii, reloaded.to_ascii().unwrap(), "level {} draws differently after a round trip", level);
+        }
+    }
+
+    #[test]
+    fn hidden_targets_are_listed_after_the_rows() {
+        let text = "box 1\n#####\n#p2=#\n#####\ntarget 1 2\n\nbox 2 in 1\n_.\n.B\n";
+        let level = LevelConfig::from_ascii(1, text).unwrap();
+        assert_eq!(level.paraboxes[0].targets, vec![(1, 2)]);
+        assert_eq!(level.paraboxes[1].targets, vec![(0, 0), (1, 1)]);
+        assert_eq!(level.paraboxes[1].outer, Some(0));
+        assert_eq!(level.to_ascii().unwrap(), text);
+    }
+
+    #[test]
+    fn clones_keep_their_exit_through_every_format() {
+        let text = "box 1\n#######\n#2p..2#\n#.....#\n#######\n\nbox 2 in 1\n...\n.2.\n...\nexit 1 5\n";
+        let level = LevelConfig::from_ascii(1, text).unwrap();
+        assert_eq!(level.to_ascii().unwrap(), text);
+        let from_json = LevelFile::parse("clones.json", &level.to_json()).unwrap().to_level_config(1).unwrap();
+        assert_eq!(from_json, level);
+        assert_eq!(LevelConfig::from_original(1, &level.to_original()).unwrap(), level);
+    }
+
+    #[test]
+    fn infinity_and_epsilon_boxes_survive_every_format() {
+        let text = "box 1 in 1\n...\n.p1\n.3.\n\nbox 2\n#####\n#.1.#\n#####\ninfinity 1 2\n\nbox 3 in 1\nepsilon_of 1\n..\n..\n";
+        let level = LevelConfig::from_ascii(1, text).unwrap();
+        assert_eq!(level.paraboxes[1].find_at(1, 2), Some(&Square::Infinity(0)));
+        assert_eq!(level.paraboxes[2].epsilon_of, Some(0));
+        let from_json = LevelFile::parse("infinity.json", &level.to_json()).unwrap().to_level_config(1).unwrap();
+        assert_eq!(from_json, level);
+        assert_eq!(LevelConfig::from_original(1, &level.to_original()).unwrap(), level);
+    }
+
+    #[test]
+    fn flipped_boxes_survive_every_format() {
+        let text = "box 1\n####\n#p2#\n####\n\nbox 2 in 1\nb.\n..\nflipped\n";
+        let level = LevelConfig::from_ascii(1, text).unwrap();
+        assert!(level.paraboxes[1].flipped());
+        assert_eq!(level.to_ascii().unwrap(), text);
+        let from_json = LevelFile::parse("flipped.json", &level.to_json()).unwrap().to_level_config(1).unwrap();
+        assert_eq!(from_json, level);
+        assert_eq!(LevelConfig::from_original(1, &level.to_original()).unwrap(), level);
+    }
+
+    #[test]
+    fn locked_boxes_survive_json_but_not_the_original_format() {
+        let text = "box 1\n####\n#p2#\n####\n\nbox 2 in 1\n..\nlocked\n";
+        let level = LevelConfig::from_ascii(1, text).unwrap();
+        assert_eq!(level.to_ascii().unwrap(), text);
+        let from_json = LevelFile::parse("locked.json", &level.to_json()).unwrap().to_level_config(1).unwrap();
+        assert_eq!(from_json, level);
+        assert!(!LevelConfig::from_original(1, &level.to_original()).unwrap().paraboxes[1].locked());
+    }
+
+    #[test]
+    fn player_boxes_survive_every_format() {
+        let text = "box 1\n####\n#2=#\n####\n\nbox 2 in 1\nb.\n..\nplayer\n";
+        let level = LevelConfig::from_ascii(1, text).unwrap();
+        assert_eq!(level.players, vec![(0, (1, 1))]);
+        assert_eq!(level.to_ascii().unwrap(), text);
+        let from_json = LevelFile::parse("player.json", &level.to_json()).unwrap().to_level_config(1).unwrap();
+        assert_eq!(from_json, level);
+        assert_eq!(LevelConfig::from_original(1, &level.to_original()).unwrap(), level);
+    }
+
+    #[test]
+    fn typed_targets_survive_json_but_not_the_original_format() {
+        let text = "box 1\n######\n#p2.p#\n######\ntarget 1 3 block\ntarget 1 2 box 2\nplayer_target 1 1 2\n\nbox 2 in 1\n#\n";
+        let level = LevelConfig::from_ascii(1, text).unwrap();
+        assert_eq!(level.paraboxes[0].typed_target_at((1, 1)), Some(Target::Player { number: 1 }));
+        assert_eq!(level.to_ascii().unwrap(), text);
+        let from_json = LevelFile::parse("typed.json", &level.to_json()).unwrap().to_level_config(1).unwrap();
+        assert_eq!(from_json, level);
+        let from_original = LevelConfig::from_original(1, &level.to_original()).unwrap();
+        assert_eq!(from_original.paraboxes[0].typed_target_at((1, 1)), None);
+    }
+
+    #[test]
+    fn players_keep_their_numbers_through_every_format_once_out_of_order() {
+        let text = "box 1\n#####\n#p.p#\n#####\nplayer_target 1 3 1\n";
+        let mut level = LevelConfig::from_ascii(1, text).unwrap();
+        assert!(!level.check_win());
+        level.players.reverse();
+        assert!(level.check_win());
+        let ascii = level.to_ascii().unwrap();
+        assert!(ascii.contains("player 1 1 2\nplayer 1 3 1\n"));
+        assert_eq!(LevelConfig::from_ascii(1, &ascii).unwrap(), level);
+        let from_json = LevelFile::parse("numbered.json", &level.to_json()).unwrap().to_level_config(1).unwrap();
+        assert_eq!(from_json, level);
+    }
+
+    #[test]
+    fn level_settings_survive_json_and_ascii() {
+        let text = "max_move_depth 8\nrules sokoban max_push_chain 2\nwin reach 1 1 2\nwin within 5\n\nbox 1\n####\n#p.#\n####\n";
+        let level = LevelConfig::from_ascii(1, text).unwrap();
+        assert_eq!(level.rules.max_push_chain, Some(2));
+        assert_eq!(level.to_ascii().unwrap(), text.replace("sokoban max_push_chain 2", "parabox allow_enter false allow_exit false allow_eat false max_push_chain 2"));
+        let from_json = LevelFile::parse("settings.json", &level.to_json()).unwrap().to_level_config(1).unwrap();
+        assert_eq!(from_json, level);
+        assert_eq!(LevelConfig::from_ascii(1, &level.to_ascii().unwrap()).unwrap(), level);
+    }
+
+    #[test]
+    fn unknown_symbols_are_reported_with_their_position() {
+        let error = LevelConfig::from_ascii(1, "box 1\n#p#\n#x#\n").unwrap_err();
+        assert_eq!(error.to_string(), "line 3, column 2: expected one of . # b B p P _ = 1-9, found 'x'");
+    }
+}
@@ -0,0 +1,1032 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+
+use super::{LevelConfig, LevelLoadError, Location, Parabox, Rules, Side, Square, Target, WinCondition};
+
+/// The level format version written by this build.
+pub const LEVEL_FORMAT_VERSION: u32 = 2;
+
+// Widest line `to_json` packs values onto before breaking them up
+const MAX_LINE_WIDTH: usize = 100;
+
+/// A level file in format v2, as stored on disk.
+///
+/// ```json
+/// {
+///     "version": 2,
+///     "metadata": { "name": "First steps" },
+///     "paraboxes": [
+///         {
+///             "size": [7, 7],
+///             "outer": null,
+///             "walls": [[0, 0], [0, 1]],
+///             "cells": [{ "pos": [3, 5], "type": "Block" }, { "pos": [4, 2], "type": "Player" }],
+///             "targets": [[5, 5]],
+///             "player_targets": [[1, 1]]
+///         }
+///     ]
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct LevelFile {
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<LevelMetadata>,
+    /// Pushes and entries allowed within a single move, for levels that need more (or want
+    /// fewer) than the default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_move_depth: Option<usize>,
+    /// Which moves the level allows, if not all of them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rules: Option<RulesFile>,
+    /// What winning takes, such as `{ "type": "Reach", "id": 1, "pos": [0, 2] }`,
+    /// `{ "type": "Inside", "id": 2, "outer": 0 }`, `{ "type": "Empty", "id": 1 }` or
+    /// `{ "type": "WithinMoves", "moves": 20 }`; filling every target if there are none.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub win_conditions: Vec<WinCondition>,
+    pub paraboxes: Vec<ParaboxFile>,
+}
+
+/// Optional information about a level that does not affect how it plays.
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct LevelMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// The `rules` of a level: a `preset` of `"parabox"` (the default) or `"sokoban"`, with any
+/// rules set alongside it taking its place, as in `{ "preset": "sokoban", "max_push_chain": 2 }`.
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RulesFile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preset: Option<RulesPreset>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allow_enter: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allow_exit: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allow_eat: Option<bool>,
+    /// Whether players may pull squares along by holding Shift; off in both presets.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allow_pull: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_push_chain: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RulesPreset {
+    Parabox,
+    Sokoban,
+}
+
+impl RulesFile {
+    // The rules these stand for, with those left unset taken from the preset.
+    fn to_rules(&self) -> Rules {
+        let preset = match self.preset {
+            None | Some(RulesPreset::Parabox) => Rules::PARABOX,
+            Some(RulesPreset::Sokoban) => Rules::SOKOBAN,
+        };
+        Rules {
+            allow_enter: self.allow_enter.unwrap_or(preset.allow_enter),
+            allow_exit: self.allow_exit.unwrap_or(preset.allow_exit),
+            allow_eat: self.allow_eat.unwrap_or(preset.allow_eat),
+            allow_pull: self.allow_pull.unwrap_or(preset.allow_pull),
+            max_push_chain: self.max_push_chain.or(preset.max_push_chain),
+        }
+    }
+
+    // `rules` written as the preset they match, or as the rules that differ from full Parabox,
+    // or `None` for full Parabox rules.
+    pub(super) fn from_rules(rules: Rules) -> Option<Self> {
+        if rules == Rules::PARABOX {
+            return None;
+        }
+        if rules == Rules::SOKOBAN {
+            return Some(RulesFile { preset: Some(RulesPreset::Sokoban), ..RulesFile::default() });
+        }
+        let differs = |allowed: bool| (!allowed).then_some(false);
+        Some(RulesFile {
+            preset: None,
+            allow_enter: differs(rules.allow_enter),
+            allow_exit: differs(rules.allow_exit),
+            allow_eat: differs(rules.allow_eat),
+            allow_pull: rules.allow_pull.then_some(true),
+            max_push_chain: rules.max_push_chain,
+        })
+    }
+}
+
+/// One parabox; its id is its index in `LevelFile::paraboxes`.
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ParaboxFile {
+    pub size: (i32, i32),
+    #[serde(default)]
+    pub outer: Option<i32>,
+    #[serde(default)]
+    pub walls: Vec<(i32, i32)>,
+    #[serde(default)]
+    pub cells: Vec<Cell>,
+    #[serde(default)]
+    pub targets: Vec<(i32, i32)>,
+    /// Cells that must each hold a player to win. Files from before levels could have several
+    /// players store a single `player_target`, which is read as a list of one.
+    #[serde(default, alias = "player_target", deserialize_with = "one_or_many")]
+    pub player_targets: Vec<(i32, i32)>,
+    /// Targets that need one particular square, such as
+    /// `{ "pos": [2, 3], "needs": "Parabox", "id": 1 }`, `"needs": "Block"` or
+    /// `"needs": "Player", "number": 0`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub typed_targets: Vec<TypedTarget>,
+    /// The box whose endless entries lead into this one instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub epsilon_of: Option<i32>,
+    /// Whether the inside is mirrored left to right from how the box looks outside.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub flipped: bool,
+    /// Whether the box is the player, in levels without a `Player` cell.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub player: bool,
+    /// The box's number among the players, when they are numbered rather than counted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub player_number: Option<usize>,
+    /// Whether the box never moves: it can be entered, but stops pushes like a wall.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub locked: bool,
+    /// For some sides, the cell across it (a column of `top` and `bottom`, a row of `left` and
+    /// `right`) that squares entering through it try first.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub entry_points: BTreeMap<Side, i32>,
+}
+
+/// Everything in a parabox other than walls and empty space.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Cell {
+    pub pos: (i32, i32),
+    #[serde(flatten)]
+    pub content: CellContent,
+}
+
+/// A target cell and the square it needs.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TypedTarget {
+    pub pos: (i32, i32),
+    #[serde(flatten)]
+    pub needs: Target,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "type")]
+pub enum CellContent {
+    Block,
+    Parabox { id: i32 },
+    /// A further placement of parabox `id`; its `Parabox` cell stays the way out.
+    Clone { id: i32 },
+    /// A clone of parabox `id` that squares come out of when leaving it never ends.
+    Infinity { id: i32 },
+    /// A player. Players are numbered in the order they are listed, the boxes that are players
+    /// last, unless they all give a `number`.
+    Player {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        number: Option<usize>,
+    },
+}
+
+impl LevelFile {
+    /// Parse a level file of any supported version.
+    pub fn parse(file: &str, text: &str) -> Result<Self, LevelLoadError> {
+        let json_error = |error| LevelLoadError::Json { file: file.to_string(), error };
+        let data: Value = serde_json::from_str(text).map_err(json_error)?;
+        match data.get("version") {
+            // Version 1 files predate the version field
+            None => LevelFile::from_v1(&data),
+            Some(version) if version.as_u64() == Some(LEVEL_FORMAT_VERSION as u64) => {
+                check_v2(&data)?;
+                LevelFile::deserialize(&data).map_err(json_error)
+            }
+            Some(_) => Err(LevelLoadError::invalid("version", LEVEL_FORMAT_VERSION.to_string())),
+        }
+    }
+
+    /// Read the v1 format, where squares are keyed by strings like `"(2, 4)"` and the player is
+    /// stored as `player_pos: [box_id, x, y]`.
+    pub fn from_v1(data: &Value) -> Result<Self, LevelLoadError> {
+        let raw_paraboxes = read_array(&data["paraboxes"], "paraboxes")?;
+        let box_count = raw_paraboxes.len() as i32;
+        let player_box = read_id(&data["player_pos"][0], "player_pos[0]", box_count)?;
+        let player_pos = (
+            read_int(&data["player_pos"][1], "player_pos[1]")?,
+            read_int(&data["player_pos"][2], "player_pos[2]")?,
+        );
+        let mut paraboxes = Vec::new();
+        for (id, parabox) in raw_paraboxes.iter().enumerate() {
+            let path = format!("paraboxes[{}]", id);
+            let (size_x, size_y) = read_pair(&parabox["size"], &format!("{}.size", path))?;
+            let mut new_parabox = ParaboxFile { size: (size_x, size_y), ..ParaboxFile::default() };
+            if !parabox["outer"].is_null() {
+                new_parabox.outer = Some(read_id(&parabox["outer"], &format!("{}.outer", path), box_count)?);
+            }
+            let map = &parabox["map"];
+            if !map.is_object() {
+                return Err(LevelLoadError::invalid(&format!("{}.map", path), "an object"));
+            }
+            for (i, wall_pos) in read_array(&map["walls"], &format!("{}.map.walls", path))?.iter().enumerate() {
+                new_parabox.walls.push(read_pair(wall_pos, &format!("{}.map.walls[{}]", path, i))?);
+            }
+            for x in 0..size_x {
+                for y in 0..size_y {
+                    let key = format!("({}, {})", x, y);
+                    if let Some(square) = map.get(&key) {
+                        let square_path = format!("{}.map[{:?}]", path, key);
+                        let content = match square["type"].as_str() {
+                            Some("Block") => CellContent::Block,
+                            Some("Parabox") => CellContent::Parabox {
+                                id: read_id(&square["id"], &format!("{}.id", square_path), box_count)?,
+                            },
+                            _ => return Err(LevelLoadError::invalid(&format!("{}.type", square_path), "\"Block\" or \"Parabox\"")),
+                        };
+                        new_parabox.cells.push(Cell { pos: (x, y), content });
+                    }
+                }
+            }
+            if id as i32 == player_box {
+                new_parabox.cells.push(Cell { pos: player_pos, content: CellContent::Player { number: None } });
+            }
+            for (i, target) in read_array(&parabox["targets"], &format!("{}.targets", path))?.iter().enumerate() {
+                new_parabox.targets.push(read_pair(target, &format!("{}.targets[{}]", path, i))?);
+            }
+            if !parabox["player_target"].is_null() {
+                new_parabox.player_targets.push(read_pair(&parabox["player_target"], &format!("{}.player_target", path))?);
+            }
+            paraboxes.push(new_parabox);
+        }
+        Ok(LevelFile {
+            version: LEVEL_FORMAT_VERSION,
+            metadata: None,
+            max_move_depth: None,
+            rules: None,
+            win_conditions: Vec::new(),
+            paraboxes,
+        })
+    }
+
+    /// Build the playable level, checking everything serde cannot: ids, bounds and the player.
+    pub fn to_level_config(&self, level: i32) -> Result<LevelConfig, LevelLoadError> {
+        let box_count = self.paraboxes.len() as i32;
+        let check_id = |id: i32, path: String| {
+            if id < 0 || id >= box_count {
+                Err(LevelLoadError::invalid(&path, format!("a parabox id below {}", box_count)))
+            } else {
+                Ok(id)
+            }
+        };
+        // Each player with its number, if given, and the path to report it at
+        let mut players = Vec::new();
+        let mut paraboxes = Vec::new();
+        // Boxes with a `Parabox` cell so far; each may have only one
+        let mut placed = HashSet::new();
+        for (id, parabox_file) in self.paraboxes.iter().enumerate() {
+            let path = format!("paraboxes[{}]", id);
+            if parabox_file.size.0 <= 0 || parabox_file.size.1 <= 0 {
+                return Err(LevelLoadError::invalid(&format!("{}.size", path), "a positive width and height"));
+            }
+            let mut parabox = Parabox::new(id as i32, parabox_file.size);
+            let check_pos = |pos: (i32, i32), path: String| {
+                if parabox.check_inbounds(pos) {
+                    Ok(pos)
+                } else {
+                    Err(LevelLoadError::invalid(&path, format!("a position inside the {:?} box", parabox.size)))
+                }
+            };
+            let outer = match parabox_file.outer {
+                Some(outer) => Some(check_id(outer, format!("{}.outer", path))?),
+                None => None,
+            };
+            let epsilon_of = match parabox_file.epsilon_of {
+                Some(epsilon_of) => Some(check_id(epsilon_of, format!("{}.epsilon_of", path))?),
+                None => None,
+            };
+            if epsilon_of.is_some() && self.paraboxes[..id].iter().any(|other| other.epsilon_of == epsilon_of) {
+                return Err(LevelLoadError::invalid(&format!("{}.epsilon_of", path), "a box with no other epsilon box"));
+            }
+            // Positions already holding a wall or cell; each holds at most one square
+            let mut taken = HashSet::new();
+            let mut claim = |pos: (i32, i32), path: String| {
+                let pos = check_pos(pos, path.clone())?;
+                if !taken.insert(pos) {
+                    return Err(LevelLoadError::invalid(&path, "a position not already holding a wall or cell"));
+                }
+                Ok(pos)
+            };
+            let mut walls = Vec::new();
+            for (i, &pos) in parabox_file.walls.iter().enumerate() {
+                walls.push(claim(pos, format!("{}.walls[{}]", path, i))?);
+            }
+            let mut cells = Vec::new();
+            for (i, cell) in parabox_file.cells.iter().enumerate() {
+                let cell_path = format!("{}.cells[{}]", path, i);
+                let pos = claim(cell.pos, format!("{}.pos", cell_path))?;
+                match cell.content {
+                    CellContent::Block => cells.push((pos, Square::Block)),
+                    CellContent::Parabox { id } => {
+                        let id = check_id(id, format!("{}.id", cell_path))?;
+                        if !placed.insert(id) {
+                            return Err(LevelLoadError::invalid(
+                                &format!("{}.type", cell_path),
+                                format!("\"Clone\", box {} already has a Parabox cell", id + 1),
+                            ));
+                        }
+                        cells.push((pos, Square::Parabox(id)));
+                    }
+                    CellContent::Clone { id } => cells.push((pos, Square::Clone(check_id(id, format!("{}.id", cell_path))?))),
+                    CellContent::Infinity { id } => cells.push((pos, Square::Infinity(check_id(id, format!("{}.id", cell_path))?))),
+                    CellContent::Player { number } => {
+                        players.push(((id as i32, pos), number, format!("{}.number", cell_path)));
+                        cells.push((pos, Square::Player));
+                    }
+                }
+            }
+            let mut targets = Vec::new();
+            for (i, &pos) in parabox_file.targets.iter().enumerate() {
+                targets.push(check_pos(pos, format!("{}.targets[{}]", path, i))?);
+            }
+            let mut player_targets = Vec::new();
+            for (i, &pos) in parabox_file.player_targets.iter().enumerate() {
+                player_targets.push(check_pos(pos, format!("{}.player_targets[{}]", path, i))?);
+            }
+            let mut typed_targets = Vec::new();
+            for (i, target) in parabox_file.typed_targets.iter().enumerate() {
+                let target_path = format!("{}.typed_targets[{}]", path, i);
+                if let Target::Parabox { id } = target.needs {
+                    check_id(id, format!("{}.id", target_path))?;
+                }
+                typed_targets.push((check_pos(target.pos, format!("{}.pos", target_path))?, target.needs));
+            }
+
+            parabox.set_outer(outer);
+            parabox.set_epsilon_of(epsilon_of);
+            parabox.set_flipped(parabox_file.flipped);
+            parabox.set_player(parabox_file.player);
+            if parabox_file.locked && parabox_file.player {
+                return Err(LevelLoadError::invalid(&format!("{}.locked", path), "false for a box that is the player"));
+            }
+            parabox.set_locked(parabox_file.locked);
+            for (&side, &i) in &parabox_file.entry_points {
+                let (width, expected) = match side {
+                    Side::Top | Side::Bottom => (parabox.size.1, "a column of the box"),
+                    Side::Left | Side::Right => (parabox.size.0, "a row of the box"),
+                };
+                if i < 0 || i >= width {
+                    return Err(LevelLoadError::invalid(&format!("{}.entry_points.{}", path, side), expected));
+                }
+                parabox.set_entry_point(side, i);
+            }
+            for pos in walls {
+                parabox.add_square(pos, Square::Wall);
+            }
+            for (pos, square) in cells {
+                parabox.add_square(pos, square);
+            }
+            for (x, y) in targets {
+                parabox.add_target(x, y);
+            }
+            for (x, y) in player_targets {
+                parabox.add_player_target(x, y);
+            }
+            for (pos, target) in typed_targets {
+                parabox.add_typed_target(pos, target);
+            }
+            paraboxes.push(parabox);
+        }
+        for (id, parabox_file) in self.paraboxes.iter().enumerate() {
+            let number_path = format!("paraboxes[{}].player_number", id);
+            if !parabox_file.player {
+                if parabox_file.player_number.is_some() {
+                    return Err(LevelLoadError::invalid(&number_path, "null for a box that is not the player"));
+                }
+                continue;
+            }
+            let path = format!("paraboxes[{}].player", id);
+            let placement = paraboxes.iter().find_map(|parabox| Some((parabox.id, parabox.find_box(id as i32)?)));
+            let placement = placement.ok_or_else(|| LevelLoadError::invalid(&path, "a box with a Parabox cell"))?;
+            players.push((placement, parabox_file.player_number, number_path));
+        }
+        if players.is_empty() {
+            return Err(LevelLoadError::invalid("paraboxes", "a cell of type \"Player\""));
+        }
+        let players = number_players(players)?;
+        for (id, parabox_file) in self.paraboxes.iter().enumerate() {
+            for (i, target) in parabox_file.typed_targets.iter().enumerate() {
+                if let Target::Player { number } = target.needs
+                    && number >= players.len()
+                {
+                    let path = format!("paraboxes[{}].typed_targets[{}].number", id, i);
+                    return Err(LevelLoadError::invalid(&path, format!("a player number below {}", players.len())));
+                }
+            }
+        }
+        if self.max_move_depth == Some(0) {
+            return Err(LevelLoadError::invalid("max_move_depth", "a positive number"));
+        }
+        for (i, condition) in self.win_conditions.iter().enumerate() {
+            let path = format!("win_conditions[{}]", i);
+            match *condition {
+                WinCondition::Targets | WinCondition::WithinMoves { .. } => {}
+                WinCondition::Reach { id, pos } => {
+                    let id = check_id(id, format!("{}.id", path))?;
+                    if !paraboxes[id as usize].check_inbounds(pos) {
+                        return Err(LevelLoadError::invalid(&format!("{}.pos", path), format!("a position inside the {:?} box", paraboxes[id as usize].size)));
+                    }
+                }
+                WinCondition::Inside { id, outer } => {
+                    check_id(id, format!("{}.id", path))?;
+                    check_id(outer, format!("{}.outer", path))?;
+                }
+                WinCondition::Empty { id } => {
+                    check_id(id, format!("{}.id", path))?;
+                }
+            }
+        }
+        let level_config = LevelConfig {
+            level,
+            metadata: self.metadata.clone(),
+            paraboxes,
+            players,
+            max_move_depth: self.max_move_depth,
+            rules: self.rules.as_ref().map(RulesFile::to_rules).unwrap_or_default(),
+            win_conditions: self.win_conditions.clone(),
+            moves: 0,
+        };
+        level_config.check_outers()?;
+        Ok(level_config)
+    }
+
+    /// The file describing `level_config` in its current state.
+    pub fn from_level_config(level_config: &LevelConfig) -> Self {
+        let paraboxes = level_config.paraboxes.iter().map(|parabox| {
+            let mut walls = Vec::new();
+            let mut cells = Vec::new();
+            for (&pos, square) in &parabox.map {
+                match square {
+                    Square::Wall => walls.push(pos),
+                    Square::Player => {
+                        let number = level_config.player_number((parabox.id, pos));
+                        cells.push(Cell { pos, content: CellContent::Player { number } });
+                    }
+                    Square::Block => cells.push(Cell { pos, content: CellContent::Block }),
+                    Square::Parabox(id) => cells.push(Cell { pos, content: CellContent::Parabox { id: *id } }),
+                    Square::Clone(id) => cells.push(Cell { pos, content: CellContent::Clone { id: *id } }),
+                    Square::Infinity(id) => cells.push(Cell { pos, content: CellContent::Infinity { id: *id } }),
+                }
+            }
+            // `map` is unordered, so sort to keep saved files stable
+            walls.sort();
+            cells.sort_by_key(|cell| cell.pos);
+            ParaboxFile {
+                size: parabox.size,
+                outer: parabox.outer,
+                walls,
+                cells,
+                targets: parabox.targets.clone(),
+                player_targets: parabox.player_targets.clone(),
+                typed_targets: parabox.typed_targets.iter().map(|&(pos, needs)| TypedTarget { pos, needs }).collect(),
+                epsilon_of: parabox.epsilon_of,
+                flipped: parabox.flipped,
+                player: parabox.player,
+                player_number: level_config.paraboxes.iter()
+                    .find_map(|outer| Some((outer.id, outer.find_box(parabox.id)?)))
+                    .filter(|_| parabox.player)
+                    .and_then(|location| level_config.player_number(location)),
+                locked: parabox.locked,
+                entry_points: parabox.entry_points.iter().map(|(&side, &i)| (side, i)).collect(),
+            }
+        }).collect();
+        LevelFile {
+            version: LEVEL_FORMAT_VERSION,
+            metadata: level_config.metadata.clone(),
+            max_move_depth: level_config.max_move_depth,
+            rules: RulesFile::from_rules(level_config.rules),
+            win_conditions: level_config.win_conditions.clone(),
+            paraboxes,
+        }
+    }
+
+    /// Serialize as JSON, keeping short arrays and objects such as `[3, 5]` on one line.
+    pub fn to_json(&self) -> String {
+        let value = serde_json::to_value(self).expect("level files always serialize");
+        let mut out = String::new();
+        write_json(&mut out, &value, 0);
+        out.push('\n');
+        out
+    }
+}
+
+// Check every field of a v2 level the way serde reads it, so that whatever is wrong is
+// reported at its path.
+fn check_v2(data: &Value) -> Result<(), LevelLoadError> {
+    let fields = ["version", "metadata", "max_move_depth", "rules", "win_conditions", "paraboxes"];
+    let level = read_object(data, "", &fields)?;
+    if let Some((metadata, path)) = read_field(level, "", "metadata").filter(|(value, _)| !value.is_null()) {
+        let metadata = read_object(metadata, &path, &["name", "author", "description"])?;
+        for name in ["name", "author", "description"] {
+            if let Some((text, path)) = read_field(metadata, &path, name).filter(|(value, _)| !value.is_null()) {
+                text.as_str().ok_or_else(|| LevelLoadError::invalid(&path, "a string"))?;
+            }
+        }
+    }
+    if let Some((depth, path)) = read_field(level, "", "max_move_depth").filter(|(value, _)| !value.is_null()) {
+        read_int(depth, &path)?;
+    }
+    if let Some((rules, path)) = read_field(level, "", "rules").filter(|(value, _)| !value.is_null()) {
+        check_rules(rules, &path)?;
+    }
+    if let Some((conditions, path)) = read_field(level, "", "win_conditions") {
+        for (i, condition) in read_array(conditions, &path)?.iter().enumerate() {
+            check_win_condition(condition, &format!("{}[{}]", path, i))?;
+        }
+    }
+    let (paraboxes, path) = require_field(level, "", "paraboxes")?;
+    for (i, parabox) in read_array(paraboxes, &path)?.iter().enumerate() {
+        check_parabox(parabox, &format!("{}[{}]", path, i))?;
+    }
+    Ok(())
+}
+
+fn check_rules(value: &Value, path: &str) -> Result<(), LevelLoadError> {
+    let rules = read_object(value, path, &["preset", "allow_enter", "allow_exit", "allow_eat", "allow_pull", "max_push_chain"])?;
+    if let Some((preset, path)) = read_field(rules, path, "preset").filter(|(value, _)| !value.is_null())
+        && !matches!(preset.as_str(), Some("parabox" | "sokoban"))
+    {
+        return Err(LevelLoadError::invalid(&path, "\"parabox\" or \"sokoban\""));
+    }
+    for name in ["allow_enter", "allow_exit", "allow_eat", "allow_pull"] {
+        if let Some((allowed, path)) = read_field(rules, path, name).filter(|(value, _)| !value.is_null()) {
+            read_bool(allowed, &path)?;
+        }
+    }
+    if let Some((limit, path)) = read_field(rules, path, "max_push_chain").filter(|(value, _)| !value.is_null()) {
+        read_int(limit, &path)?;
+    }
+    Ok(())
+}
+
+fn check_win_condition(value: &Value, path: &str) -> Result<(), LevelLoadError> {
+    let variants: &[(&str, &[&str])] = &[
+        ("Targets", &[]),
+        ("Reach", &["id", "pos"]),
+        ("Inside", &["id", "outer"]),
+        ("Empty", &["id"]),
+        ("WithinMoves", &["moves"]),
+    ];
+    let (variant, condition) = read_variant(value, path, "type", variants)?;
+    for (name, fields) in variants {
+        if *name == variant {
+            for &field in *fields {
+                let (value, path) = require_field(condition, path, field)?;
+                if field == "pos" { read_pair(value, &path)?; } else { read_int(value, &path)?; }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check_parabox(value: &Value, path: &str) -> Result<(), LevelLoadError> {
+    let fields = [
+        "size", "outer", "walls", "cells", "targets", "player_target", "player_targets", "typed_targets",
+        "epsilon_of", "flipped", "player", "player_number", "locked", "entry_points",
+    ];
+    let parabox = read_object(value, path, &fields)?;
+    let (size, size_path) = require_field(parabox, path, "size")?;
+    read_pair(size, &size_path)?;
+    for name in ["outer", "epsilon_of", "player_number"] {
+        if let Some((id, path)) = read_field(parabox, path, name).filter(|(value, _)| !value.is_null()) {
+            read_int(id, &path)?;
+        }
+    }
+    for name in ["walls", "targets"] {
+        if let Some((positions, path)) = read_field(parabox, path, name) {
+            read_pairs(positions, &path)?;
+        }
+    }
+    for name in ["player_target", "player_targets"] {
+        // Either a single pair or a list of them
+        if let Some((positions, path)) = read_field(parabox, path, name).filter(|(value, _)| !value.is_null()) {
+            if positions.get(0).is_some_and(Value::is_number) {
+                read_pair(positions, &path)?;
+            } else {
+                read_pairs(positions, &path)?;
+            }
+        }
+    }
+    for name in ["flipped", "player", "locked"] {
+        if let Some((flag, path)) = read_field(parabox, path, name) {
+            read_bool(flag, &path)?;
+        }
+    }
+    if let Some((cells, path)) = read_field(parabox, path, "cells") {
+        for (i, cell) in read_array(cells, &path)?.iter().enumerate() {
+            check_cell(cell, &format!("{}[{}]", path, i))?;
+        }
+    }
+    if let Some((targets, path)) = read_field(parabox, path, "typed_targets") {
+        for (i, target) in read_array(targets, &path)?.iter().enumerate() {
+            check_typed_target(target, &format!("{}[{}]", path, i))?;
+        }
+    }
+    if let Some((entries, path)) = read_field(parabox, path, "entry_points") {
+        for (side, i) in read_object(entries, &path, &["top", "bottom", "left", "right"])? {
+            read_int(i, &field_path(&path, side))?;
+        }
+    }
+    Ok(())
+}
+
+fn check_cell(value: &Value, path: &str) -> Result<(), LevelLoadError> {
+    let variants: &[(&str, &[&str])] = &[
+        ("Block", &["pos"]),
+        ("Parabox", &["pos", "id"]),
+        ("Clone", &["pos", "id"]),
+        ("Infinity", &["pos", "id"]),
+        ("Player", &["pos", "number"]),
+    ];
+    let (variant, cell) = read_variant(value, path, "type", variants)?;
+    let (pos, pos_path) = require_field(cell, path, "pos")?;
+    read_pair(pos, &pos_path)?;
+    if variant == "Player" {
+        if let Some((number, path)) = read_field(cell, path, "number").filter(|(value, _)| !value.is_null()) {
+            read_int(number, &path)?;
+        }
+    } else if variant != "Block" {
+        let (id, path) = require_field(cell, path, "id")?;
+        read_int(id, &path)?;
+    }
+    Ok(())
+}
+
+fn check_typed_target(value: &Value, path: &str) -> Result<(), LevelLoadError> {
+    let variants: &[(&str, &[&str])] = &[("Block", &["pos"]), ("Parabox", &["pos", "id"]), ("Player", &["pos", "number"])];
+    let (variant, target) = read_variant(value, path, "needs", variants)?;
+    let (pos, pos_path) = require_field(target, path, "pos")?;
+    read_pair(pos, &pos_path)?;
+    let needed = match variant {
+        "Parabox" => Some("id"),
+        "Player" => Some("number"),
+        _ => None,
+    };
+    if let Some(name) = needed {
+        let (id, path) = require_field(target, path, name)?;
+        read_int(id, &path)?;
+    }
+    Ok(())
+}
+
+// `value` as an object with none but the `fields` given.
+fn read_object<'a>(value: &'a Value, path: &str, fields: &[&str]) -> Result<&'a Map<String, Value>, LevelLoadError> {
+    let object = value.as_object().ok_or_else(|| LevelLoadError::invalid(if path.is_empty() { "level" } else { path }, "an object"))?;
+    if let Some(key) = object.keys().find(|key| !fields.contains(&key.as_str())) {
+        return Err(LevelLoadError::invalid(&field_path(path, key), format!("one of the fields {}", fields.join(", "))));
+    }
+    Ok(object)
+}
+
+// The object at `path` holding one of the `variants`, named by its field `tag`, with none but
+// the fields of that variant, and the variant's name.
+fn read_variant<'a>(value: &'a Value, path: &str, tag: &str, variants: &[(&'static str, &[&str])]) -> Result<(&'static str, &'a Map<String, Value>), LevelLoadError> {
+    let tag_path = field_path(path, tag);
+    let names: Vec<String> = variants.iter().map(|(name, _)| format!("{:?}", name)).collect();
+    let name = value.get(tag).and_then(Value::as_str);
+    let Some((variant, fields)) = variants.iter().find(|(variant, _)| Some(*variant) == name) else {
+        return Err(LevelLoadError::invalid(&tag_path, format!("one of {}", names.join(", "))));
+    };
+    let fields: Vec<&str> = std::iter::once(tag).chain(fields.iter().copied()).collect();
+    Ok((variant, read_object(value, path, &fields)?))
+}
+
+// Field `name` of `object`, found at `path`, and the path of the field, if it is given.
+fn read_field<'a>(object: &'a Map<String, Value>, path: &str, name: &str) -> Option<(&'a Value, String)> {
+    object.get(name).map(|value| (value, field_path(path, name)))
+}
+
+fn require_field<'a>(object: &'a Map<String, Value>, path: &str, name: &str) -> Result<(&'a Value, String), LevelLoadError> {
+    read_field(object, path, name).ok_or_else(|| LevelLoadError::invalid(&field_path(path, name), "a value"))
+}
+
+// The path of `field` in the object at `path`.
+fn field_path(path: &str, field: &str) -> String {
+    if path.is_empty() { field.to_string() } else { format!("{}.{}", path, field) }
+}
+
+// The players in the order of their numbers, if they all have one, or else in the order given.
+fn number_players(listed: Vec<(Location, Option<usize>, String)>) -> Result<Vec<Location>, LevelLoadError> {
+    if listed.iter().all(|(_, number, _)| number.is_none()) {
+        return Ok(listed.into_iter().map(|(location, _, _)| location).collect());
+    }
+    let mut players = vec![None; listed.len()];
+    for (location, number, path) in listed {
+        let number = number.ok_or_else(|| LevelLoadError::invalid(&path, "a number, as other players have one"))?;
+        match players.get_mut(number) {
+            Some(player @ None) => *player = Some(location),
+            _ => return Err(LevelLoadError::invalid(&path, format!("a player number below {} no other player has", players.len()))),
+        }
+    }
+    // Every number was below the player count and different, so every player is placed
+    Ok(players.into_iter().flatten().collect())
+}
+
+// Read either one `[x, y]` pair, `null`, or a list of pairs as a list of pairs.
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<(i32, i32)>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One((i32, i32)),
+        Many(Vec<(i32, i32)>),
+    }
+    Ok(match Option::<OneOrMany>::deserialize(deserializer)? {
+        None => Vec::new(),
+        Some(OneOrMany::One(pos)) => vec![pos],
+        Some(OneOrMany::Many(positions)) => positions,
+    })
+}
+
+/// Rewrite a level file in the current format.
+/// Returns whether the file was changed; files that are already current are left alone.
+pub fn migrate_level_file(file: &str) -> Result<bool, LevelLoadError> {
+    let io_error = |error| LevelLoadError::Io { file: file.to_string(), error };
+    let text = fs::read_to_string(file).map_err(io_error)?;
+    let data: Value = serde_json::from_str(&text).map_err(|error| LevelLoadError::Json { file: file.to_string(), error })?;
+    if data.get("version").is_some() {
+        // Already current; just make sure it is valid
+        LevelFile::parse(file, &text)?.to_level_config(0)?;
+        return Ok(false);
+    }
+    LevelFile::from_v1(&data)?.to_level_config(0)?.save(file).map_err(io_error)?;
+    Ok(true)
+}
+
+fn read_int(value: &Value, path: &str) -> Result<i32, LevelLoadError> {
+    value
+        .as_u64()
+        .and_then(|int| i32::try_from(int).ok())
+        .ok_or_else(|| LevelLoadError::invalid(path, "a non-negative integer"))
+}
+
+fn read_id(value: &Value, path: &str, box_count: i32) -> Result<i32, LevelLoadError> {
+    let id = read_int(value, path)?;
+    if id >= box_count {
+        return Err(LevelLoadError::invalid(path, format!("a parabox id below {}", box_count)));
+    }
+    Ok(id)
+}
+
+fn read_pair(value: &Value, path: &str) -> Result<(i32, i32), LevelLoadError> {
+    if read_array(value, path)?.len() != 2 {
+        return Err(LevelLoadError::invalid(path, "an array of two integers"));
+    }
+    Ok((read_int(&value[0], &format!("{}[0]", path))?, read_int(&value[1], &format!("{}[1]", path))?))
+}
+
+fn read_pairs(value: &Value, path: &str) -> Result<(), LevelLoadError> {
+    for (i, pair) in read_array(value, path)?.iter().enumerate() {
+        read_pair(pair, &format!("{}[{}]", path, i))?;
+    }
+    Ok(())
+}
+
+fn read_bool(value: &Value, path: &str) -> Result<bool, LevelLoadError> {
+    value.as_bool().ok_or_else(|| LevelLoadError::invalid(path, "true or false"))
+}
+
+fn read_array<'a>(value: &'a Value, path: &str) -> Result<&'a Vec<Value>, LevelLoadError> {
+    value.as_array().ok_or_else(|| LevelLoadError::invalid(path, "an array"))
+}
+
+// `value` on a single line, with a space after every `,` and `:`
+fn inline_json(value: &Value) -> String {
+    match value {
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(inline_json).collect();
+            format!("[{}]", items.join(", "))
+        }
+        Value::Object(map) => {
+            let entries: Vec<String> = map
+                .iter()
+                .map(|(key, value)| format!("{}: {}", Value::String(key.clone()), inline_json(value)))
+                .collect();
+            format!("{{ {} }}", entries.join(", "))
+        }
+        scalar => scalar.to_string(),
+    }
+}
+
+// Write `value` starting at column `indent`, breaking it over several lines if it is too wide.
+// Arrays of arrays (wall and target lists) are packed several items per line.
+fn write_json(out: &mut String, value: &Value, indent: usize) {
+    let inline = inline_json(value);
+    if indent + inline.len() <= MAX_LINE_WIDTH || !(value.is_array() || value.is_object()) {
+        out.push_str(&inline);
+        return;
+    }
+    let pad = " ".repeat(indent + 4);
+    match value {
+        Value::Array(items) if items.iter().all(Value::is_array) => {
+            out.push_str("[\n");
+            out.push_str(&pad);
+            let mut width = pad.len();
+            for (i, item) in items.iter().enumerate() {
+                let item = inline_json(item);
+                if i > 0 {
+                    if width + item.len() + 2 > MAX_LINE_WIDTH {
+                        out.push_str(",\n");
+                        out.push_str(&pad);
+                        width = pad.len();
+                    } else {
+                        out.push_str(", ");
+                        width += 2;
+                    }
+                }
+                out.push_str(&item);
+                width += item.len();
+            }
+            out.push('\n');
+        }
+        Value::Array(items) => {
+            out.push_str("[\n");
+            for (i, item) in items.iter().enumerate() {
+                out.push_str(&pad);
+                write_json(out, item, indent + 4);
+                out.push_str(if i + 1 < items.len() { ",\n" } else { "\n" });
+            }
+        }
+        Value::Object(map) => {
+            out.push_str("{\n");
+            for (i, (key, item)) in map.iter().enumerate() {
+                out.push_str(&pad);
+                out.push_str(&format!("{}: ", Value::String(key.clone())));
+                write_json(out, item, indent + 4);
+                out.push_str(if i + 1 < map.len() { ",\n" } else { "\n" });
+            }
+        }
+        _ => unreachable!(),
+    }
+    out.push_str(&" ".repeat(indent));
+    out.push(if value.is_array() { ']' } else { '}' });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level_path(level: i32) -> String {
+        format!("{}/assets/levels/{}.json", env!("CARGO_MANIFEST_DIR"), level)
+    }
+
+    #[test]
+    fn shipped_levels_round_trip_through_json() {
+        for level in 1..=12 {
+            let loaded = LevelConfig::try_new(level, &level_path(level)).unwrap();
+            let json = loaded.to_json();
+            let reloaded = LevelFile::parse("round trip", &json).unwrap().to_level_config(level).unwrap();
+            assert!(loaded == reloaded, "level {} changed after a round trip:\n{}", level, json);
+            assert_eq!(json, reloaded.to_json(), "level {} saves differently after a round trip", level);
+        }
+    }
+
+    #[test]
+    fn save_writes_a_loadable_file() {
+        let loaded = LevelConfig::try_new(9, &level_path(9)).unwrap();
+        let file = std::env::temp_dir().join("parabox_save_test.json");
+        let file = file.to_str().unwrap();
+        loaded.save(file).unwrap();
+        let reloaded = LevelConfig::try_new(9, file).unwrap();
+        fs::remove_file(file).unwrap();
+        assert!(loaded == reloaded);
+    }
+
+    #[test]
+    fn metadata_survives_a_round_trip() {
+        let mut loaded = LevelConfig::try_new(1, &level_path(1)).unwrap();
+        loaded.metadata = Some(LevelMetadata { name: Some("First steps".to_string()), ..Default::default() });
+        let reloaded = LevelFile::parse("round trip", &loaded.to_json()).unwrap().to_level_config(1).unwrap();
+        assert!(loaded == reloaded);
+    }
+    #[test]
+    fn an_outer_box_not_holding_the_box_is_reported() {
+        let text = r#"{ "version": 2, "paraboxes": [
+            { "size": [1, 2], "cells": [{ "pos": [0, 0], "type": "Player" }] },
+            { "size": [1, 1], "outer": 0 }
+        ] }"#;
+        let error = LevelFile::parse("outer.json", text).unwrap().to_level_config(1).unwrap_err();
+        assert_eq!(error.to_string(), "paraboxes[1].outer: expected null, as no box holds its Parabox cell");
+    }
+
+    #[test]
+    fn a_single_player_target_is_read_as_a_list() {
+        let text = r#"{ "version": 2, "paraboxes": [{ "size": [1, 2], "cells": [{ "pos": [0, 0], "type": "Player" }], "player_target": [0, 1] }] }"#;
+        let level = LevelFile::parse("old.json", text).unwrap().to_level_config(1).unwrap();
+        assert_eq!(level.paraboxes[0].player_targets, vec![(0, 1)]);
+        assert!(level.to_json().contains("\"player_targets\": [[0, 1]]"));
+    }
+
+    #[test]
+    fn a_target_for_a_missing_player_is_reported() {
+        let text = r#"{ "version": 2, "paraboxes": [{ "size": [1, 2], "cells": [{ "pos": [0, 0], "type": "Player" }],
+            "typed_targets": [{ "pos": [0, 1], "needs": "Player", "number": 1 }] }] }"#;
+        let error = LevelFile::parse("typed.json", text).unwrap().to_level_config(1).unwrap_err();
+        assert_eq!(error.to_string(), "paraboxes[0].typed_targets[0].number: expected a player number below 1");
+    }
+
+    #[test]
+    fn win_conditions_are_checked_against_the_boxes() {
+        let text = r#"{ "version": 2, "win_conditions": [{ "type": "Inside", "id": 0, "outer": 1 }],
+            "paraboxes": [{ "size": [1, 1], "cells": [{ "pos": [0, 0], "type": "Player" }] }] }"#;
+        let error = LevelFile::parse("win.json", text).unwrap().to_level_config(1).unwrap_err();
+        assert_eq!(error.to_string(), "win_conditions[0].outer: expected a parabox id below 1");
+    }
+
+    #[test]
+    fn rules_set_next_to_a_preset_take_its_place() {
+        let text = r#"{ "version": 2, "rules": { "preset": "sokoban", "max_push_chain": 2 },
+            "paraboxes": [{ "size": [1, 1], "cells": [{ "pos": [0, 0], "type": "Player" }] }] }"#;
+        let level = LevelFile::parse("rules.json", text).unwrap().to_level_config(1).unwrap();
+        assert_eq!(level.rules, Rules { max_push_chain: Some(2), ..Rules::SOKOBAN });
+        assert!(level.to_json().contains(r#""rules": { "allow_enter": false, "allow_exit": false, "allow_eat": false, "max_push_chain": 2 }"#));
+        let pulling = text.replace(r#""max_push_chain": 2"#, r#""allow_pull": true"#);
+        let level = LevelFile::parse("rules.json", &pulling).unwrap().to_level_config(1).unwrap();
+        assert_eq!(level.rules, Rules { allow_pull: true, ..Rules::SOKOBAN });
+        let reloaded = LevelFile::parse("rules.json", &level.to_json()).unwrap().to_level_config(1).unwrap();
+        assert_eq!(reloaded.rules, level.rules);
+    }
+
+    #[test]
+    fn two_squares_on_one_position_are_reported() {
+        let text = r#"{ "version": 2, "paraboxes": [{ "size": [1, 2],
+            "cells": [{ "pos": [0, 0], "type": "Player" }, { "pos": [0, 0], "type": "Block" }] }] }"#;
+        let error = LevelFile::parse("overlap.json", text).unwrap().to_level_config(1).unwrap_err();
+        assert_eq!(error.to_string(), "paraboxes[0].cells[1].pos: expected a position not already holding a wall or cell");
+    }
+
+    #[test]
+    fn players_are_numbered_all_or_none() {
+        let text = r#"{ "version": 2, "paraboxes": [{ "size": [1, 2],
+            "cells": [{ "pos": [0, 0], "type": "Player", "number": 1 }, { "pos": [0, 1], "type": "Player" }] }] }"#;
+        let error = LevelFile::parse("numbered.json", text).unwrap().to_level_config(1).unwrap_err();
+        assert_eq!(error.to_string(), "paraboxes[0].cells[1].number: expected a number, as other players have one");
+    }
+
+    #[test]
+    fn misspelled_and_mistyped_fields_are_reported_at_their_path() {
+        let level = |parabox: &str| {
+            let text = format!(r#"{{ "version": 2, "paraboxes": [{{ "size": [1, 2], "cells": [{{ "pos": [0, 0], "type": "Player" }}] }}, {}] }}"#, parabox);
+            LevelFile::parse("typo.json", &text).err().map(|error| error.to_string())
+        };
+        assert_eq!(
+            level(r#"{ "size": [1, 1], "wals": [[0, 0]] }"#).unwrap(),
+            "paraboxes[1].wals: expected one of the fields size, outer, walls, cells, targets, player_target, \
+            player_targets, typed_targets, epsilon_of, flipped, player, player_number, locked, entry_points"
+        );
+        assert_eq!(
+            level(r#"{ "size": [1, 1], "cells": [{ "pos": [0, 0], "type": "Blok" }] }"#).unwrap(),
+            r#"paraboxes[1].cells[0].type: expected one of "Block", "Parabox", "Clone", "Infinity", "Player""#
+        );
+        assert_eq!(
+            level(r#"{ "size": [1, 1], "cells": [{ "pos": [0, 0], "type": "Block", "id": 0 }] }"#).unwrap(),
+            "paraboxes[1].cells[0].id: expected one of the fields type, pos"
+        );
+        assert_eq!(level(r#"{ "outer": null }"#).unwrap(), "paraboxes[1].size: expected a value");
+    }
+
+    #[test]
+    fn mistyped_values_are_reported_at_their_field() {
+        let level = |parabox: &str| {
+            let text = format!(r#"{{ "version": 2, "paraboxes": [{{ "size": [1, 2], "cells": [{{ "pos": [0, 0], "type": "Player" }}] }}, {}] }}"#, parabox);
+            LevelFile::parse("typo.json", &text).err().map(|error| error.to_string())
+        };
+        assert_eq!(level(r#"{ "size": [1, "a"] }"#).unwrap(), "paraboxes[1].size[1]: expected a non-negative integer");
+        assert_eq!(level(r#"{ "size": [1, 1], "outer": "0" }"#).unwrap(), "paraboxes[1].outer: expected a non-negative integer");
+        assert_eq!(level(r#"{ "size": [1, 1], "walls": [[0, 0], 1] }"#).unwrap(), "paraboxes[1].walls[1]: expected an array");
+        assert_eq!(level(r#"{ "size": [1, 1], "flipped": 1 }"#).unwrap(), "paraboxes[1].flipped: expected true or false");
+        let text = r#"{ "version": 2, "max_move_depth": -1, "paraboxes": [] }"#;
+        let error = LevelFile::parse("typo.json", text).err().unwrap();
+        assert_eq!(error.to_string(), "max_move_depth: expected a non-negative integer");
+    }
+
+    #[test]
+    fn v1_squares_of_unknown_type_are_reported_at_their_key() {
+        let text = r#"{ "player_pos": [0, 0, 0], "paraboxes": [
+            { "size": [1, 2], "outer": null, "map": { "walls": [] }, "targets": [] },
+            { "size": [1, 2], "outer": 0, "map": { "walls": [], "(0, 1)": { "type": "Wall" } }, "targets": [] }
+        ] }"#;
+        let error = LevelFile::parse("old.json", text).err().unwrap();
+        assert_eq!(error.to_string(), r#"paraboxes[1].map["(0, 1)"].type: expected "Block" or "Parabox""#);
+    }
+}
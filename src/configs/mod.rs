@@ -0,0 +1,1492 @@
+mod ascii;
+mod level_file;
+mod original;
+
+use std::collections::HashMap;
+use std::fs;
+use std::fmt::{Debug, Display};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub use level_file::{migrate_level_file, LevelFile, LevelMetadata};
+
+// Pushes and entries allowed within a single move before it is rejected, unless the level
+// file sets its own `max_move_depth`.
+const DEFAULT_MAX_MOVE_DEPTH: usize = 64;
+
+// Most moves into cells one player's move may try in all, counting those that fail and are
+// tried again elsewhere, so that boxes whose entries keep falling back to other cells cannot
+// stall the game.
+const MAX_MOVE_ATTEMPTS: usize = 4096;
+
+// A cell of the level as (box_id, (x, y)).
+type Location = (i32, (i32, i32));
+
+// A cell being moved into along a direction, arriving at an offset across it. Meeting the same
+// one twice within one chain of pushes and entries means the chain goes round forever.
+type Visit = (Location, (i32, i32), Offset);
+
+// The pushes and entries under way for one player's move.
+#[derive(Default)]
+struct Trail {
+    visits: Vec<Visit>, // The cells they are waiting on, innermost last
+    pushed: usize, // How many squares are being pushed along in a chain by the player
+    attempts: usize, // How many moves into cells have been tried, failed ones included
+}
+
+// Where a step lands: the cell, the boxes exited on the way, and the direction and offset the
+// square arrives with.
+type Arrival = (Location, Vec<i32>, (i32, i32), Offset);
+
+// A cell just outside a side of a parabox, with how far across the cell inside it a square
+// entering from there lands.
+type Entry = ((i32, i32), Offset);
+
+#[derive(Resource, Clone, Default, PartialEq)]
+pub struct LevelConfig {
+    level: i32,
+    metadata: Option<LevelMetadata>,
+    pub paraboxes: Vec<Parabox>,
+    players: Vec<Location>, // Where each player is, all moved by the same input
+    max_move_depth: Option<usize>,
+    rules: Rules, // Which kinds of moves the level allows
+    win_conditions: Vec<WinCondition>, // What winning takes; filling every target if empty
+    moves: usize, // Moves made so far
+}
+
+impl LevelConfig {
+    /// Load a level from a JSON file in any supported format version,
+    /// reporting the JSON path of the first invalid field.
+    pub fn try_new(level: i32, file: &str) -> Result<Self, LevelLoadError> {
+        let text = fs::read_to_string(file).map_err(|error| LevelLoadError::Io { file: file.to_string(), error })?;
+        LevelFile::parse(file, &text)?.to_level_config(level)
+    }
+
+    pub fn load(&mut self, level: i32) -> Result<(), LevelLoadError> {
+        let file = format!("assets/levels/{}.json", level);
+        let new_level = LevelConfig::try_new(level, &file)?;
+        *self = new_level;
+        Ok(())
+    }
+
+    /// The level in the current file format, which `try_new` reads back unchanged.
+    pub fn to_json(&self) -> String {
+        LevelFile::from_level_config(self).to_json()
+    }
+
+    pub fn save(&self, file: &str) -> std::io::Result<()> {
+        fs::write(file, self.to_json())
+    }
+
+    /// Move the player one square along `dir`, committing the result only if the whole move succeeds.
+    pub fn shift(&mut self, dir: (i32, i32)) -> Result<MoveReport, MoveError> {
+        let (next, report) = self.step(dir)?;
+        *self = next;
+        Ok(report)
+    }
+
+    /// Move the players one square along `dir` like `shift`, each dragging the square behind
+    /// them into the cell they leave, if the level's rules allow pulling.
+    pub fn pull(&mut self, dir: (i32, i32)) -> Result<MoveReport, MoveError> {
+        if !self.rules.allow_pull {
+            return Err(MoveError::Forbidden { rule: "pulling" });
+        }
+        let (next, report) = self.resolve(dir, true)?;
+        *self = next;
+        Ok(report)
+    }
+
+    /// Resolve a player move along `dir` without modifying `self`.
+    /// Every player moves in turn, the one furthest along `dir` first so that players walking
+    /// in a line follow each other; ties go to the lower box id, then the lower position.
+    /// Players that an earlier player already pushed along have moved and do not move again;
+    /// players that cannot move stay put. Returns the level as it would be after the move
+    /// together with a report of what moved, or why the first player could not move if none can.
+    pub fn step(&self, dir: (i32, i32)) -> Result<(LevelConfig, MoveReport), MoveError> {
+        self.resolve(dir, false)
+    }
+
+    // `step`, with every player that moves `pulling` the square behind them along if set.
+    fn resolve(&self, dir: (i32, i32), pulling: bool) -> Result<(LevelConfig, MoveReport), MoveError> {
+        // Detemine if shift is valid
+        assert!((dir.0 == 0 || dir.1 == 0), "Invalid shift: {:?}", dir);
+        assert!((dir.0.abs() <= 1 && dir.1.abs() <= 1), "Shift too large: {:?}", dir);
+
+        let mut next = self.clone();
+        next.moves += 1;
+        let mut report = MoveReport { dir, ..default() };
+        let mut order: Vec<usize> = (0..self.players.len()).collect();
+        order.sort_by_key(|&i| {
+            let (box_id, (x, y)) = self.players[i];
+            (-(x * dir.0 + y * dir.1), box_id, (x, y))
+        });
+        let mut first_error = None;
+        // Players that have moved this step, on their own turn or pushed along by another
+        let mut moved = vec![false; self.players.len()];
+        for i in order {
+            if moved[i] {
+                continue;
+            }
+            let (box_id, (x, y)) = next.players[i];
+            let Some(player) = next.paraboxes[box_id as usize].find_at(x, y).cloned() else {
+                return Err(MoveError::PlayerMissing { box_id, pos: (x, y) });
+            };
+            let from = next.players[i];
+            let before_turn = next.players.clone();
+            match next.attempt(&mut report, |level, report| level.push(player, from, dir, &mut Trail::default(), report)) {
+                Ok(()) if pulling => next.drag(from, dir, &mut report),
+                Ok(()) => {}
+                Err(error) => {
+                    first_error.get_or_insert(error);
+                }
+            }
+            for (moved, (before, after)) in moved.iter_mut().zip(before_turn.iter().zip(&next.players)) {
+                *moved |= before != after;
+            }
+        }
+        if report.moves.is_empty() {
+            return Err(first_error.unwrap_or(MoveError::NoPlayer));
+        }
+        report.satisfied_targets = next.filled_targets()
+            .into_iter()
+            .filter(|target| !self.filled_targets().contains(target))
+            .collect();
+        Ok((next, report))
+    }
+
+    // Move `square` at `from` one step along `dir`.
+    // `trail` holds the cells the pushes and entries already under way for this move are
+    // waiting on.
+    fn push(&mut self, square: Square, from: Location, dir: (i32, i32), trail: &mut Trail, report: &mut MoveReport) -> Result<(), MoveError> {
+        let (to, exited, dir, offset) = self.neighbour(from, dir)?;
+        let square_move = SquareMove { square, from, to, exited, entered: Vec::new() };
+        self.move_into(square_move, dir, offset, trail, report)
+    }
+
+    // Carry out `square_move`, unless its destination is already on the `trail`, which would
+    // make the move go round forever, or the trail has grown past the level's depth limit, or
+    // the move has tried too many cells in all.
+    fn move_into(&mut self, square_move: SquareMove, dir: (i32, i32), offset: Offset, trail: &mut Trail, report: &mut MoveReport) -> Result<(), MoveError> {
+        let visit = (square_move.to, dir, offset);
+        if trail.visits.contains(&visit) {
+            return Err(MoveError::Cycle { box_id: square_move.to.0, pos: square_move.to.1 });
+        }
+        trail.attempts += 1;
+        if trail.visits.len() >= self.max_move_depth.unwrap_or(DEFAULT_MAX_MOVE_DEPTH) || trail.attempts > MAX_MOVE_ATTEMPTS {
+            return Err(MoveError::RecursionLimit);
+        }
+        trail.visits.push(visit);
+        let result = self.make_room_and_place(square_move, dir, offset, trail, report);
+        trail.visits.pop();
+        result
+    }
+
+    // Make room in the destination of `square_move` by pushing whatever is there, then apply it.
+    // If the occupant cannot be pushed and is a parabox, or is a locked parabox, the square
+    // tries to enter it instead.
+    // Failing that, a square that is itself a parabox tries to eat the occupant, unless it is locked.
+    // The level's rules may rule out pushing too many squares at once, entering and eating.
+    fn make_room_and_place(&mut self, square_move: SquareMove, dir: (i32, i32), offset: Offset, trail: &mut Trail, report: &mut MoveReport) -> Result<(), MoveError> {
+        let to = square_move.to;
+        match self.paraboxes[to.0 as usize].find_at(to.1 .0, to.1 .1).cloned() {
+            None => {}
+            Some(Square::Wall) => return Err(MoveError::BlockedByWall { box_id: to.0, pos: to.1 }),
+            Some(occupant) => {
+                let locked = matches!(
+                    occupant,
+                    Square::Parabox(id) | Square::Clone(id) | Square::Infinity(id) if self.paraboxes[id as usize].locked
+                );
+                // A locked box is not even tried, as pushing it always fails
+                let pushed = (!locked).then(|| match self.rules.max_push_chain {
+                    Some(limit) if trail.pushed >= limit => Err(MoveError::PushChainTooLong { limit }),
+                    _ => {
+                        trail.pushed += 1;
+                        let result = self.attempt(report, |level, report| level.push(occupant.clone(), to, dir, trail, report));
+                        trail.pushed -= 1;
+                        result
+                    }
+                });
+                let reason = match (pushed, &occupant) {
+                    (Some(Ok(())), _) => None,
+                    (Some(Err(MoveError::RecursionLimit)), _) => return Err(MoveError::RecursionLimit),
+                    (_, Square::Parabox(id) | Square::Clone(id) | Square::Infinity(id)) if self.rules.allow_enter => {
+                        match self.enter(&square_move, *id, dir, offset, trail, report) {
+                            Ok(()) => return Ok(()),
+                            Err(error @ (MoveError::RecursionLimit | MoveError::EndlessEntry { .. })) => return Err(error),
+                            Err(reason) => Some(reason),
+                        }
+                    }
+                    (Some(Err(reason)), _) => Some(reason),
+                    (None, _) => Some(MoveError::Forbidden { rule: "entering boxes" }),
+                };
+                if let Some(reason) = reason {
+                    let (Square::Parabox(eater) | Square::Clone(eater) | Square::Infinity(eater)) = square_move.square else {
+                        return Err(reason);
+                    };
+                    if locked || !self.rules.allow_eat {
+                        return Err(reason);
+                    }
+                    match self.attempt(report, |level, report| level.eat(occupant, to, eater, dir, trail, report)) {
+                        Ok(()) => {}
+                        Err(MoveError::RecursionLimit) => return Err(MoveError::RecursionLimit),
+                        Err(_) => return Err(reason),
+                    }
+                }
+            }
+        }
+        self.place(&square_move);
+        report.moves.push(square_move);
+        Ok(())
+    }
+
+    // Drag the square behind a player who has just left `from` along `dir` into the cell they
+    // left. A player at the edge of a box drags the square outside that edge into the box, out
+    // of the box holding it. Squares are never dragged the other way, out of a box into the one
+    // holding it. Walls, players and locked boxes stay put, as does anything when the cell has
+    // been taken or the level's rules forbid the way there.
+    fn drag(&mut self, from: Location, dir: (i32, i32), report: &mut MoveReport) {
+        let Ok((behind, exited, _, _)) = self.neighbour(from, (-dir.0, -dir.1)) else {
+            return;
+        };
+        if self.paraboxes[from.0 as usize].find_at(from.1 .0, from.1 .1).is_some() || (!exited.is_empty() && !self.rules.allow_enter) {
+            return;
+        }
+        let Some(square) = self.paraboxes[behind.0 as usize].find_at(behind.1 .0, behind.1 .1).cloned() else {
+            return;
+        };
+        let draggable = match square {
+            Square::Block => true,
+            Square::Parabox(id) | Square::Clone(id) | Square::Infinity(id) => !self.paraboxes[id as usize].locked && !self.is_player(&square),
+            Square::Wall | Square::Player => false,
+        };
+        if draggable {
+            // The boxes walked out of to find the square are the ones it is dragged into
+            let square_move = SquareMove { square, from: behind, to: from, exited: Vec::new(), entered: exited.into_iter().rev().collect() };
+            self.place(&square_move);
+            report.moves.push(square_move);
+        }
+    }
+
+    // Move the square of `square_move` into parabox `id`, which sits in its destination, at the
+    // point of its side matching `offset` across the destination cell. Entering a box already
+    // entered on this move would go on forever, so the square goes into that box's epsilon box
+    // instead.
+    fn enter(&mut self, square_move: &SquareMove, id: i32, dir: (i32, i32), offset: Offset, trail: &mut Trail, report: &mut MoveReport) -> Result<(), MoveError> {
+        let id = if !square_move.entered.contains(&id) {
+            id
+        } else {
+            match self.epsilon_box(id) {
+                Some(epsilon) if !square_move.entered.contains(&epsilon) => epsilon,
+                _ => return Err(MoveError::EndlessEntry { box_id: id }),
+            }
+        };
+        let mut entering = square_move.clone();
+        entering.entered.push(id);
+        match self.move_into_box(entering, id, dir, offset, trail, report) {
+            Err(error @ (MoveError::RecursionLimit | MoveError::EndlessEntry { .. })) => Err(error),
+            Err(_) => Err(MoveError::EntryBlocked { box_id: id, side: Side::entered_by(dir) }),
+            Ok(()) => Ok(()),
+        }
+    }
+
+    // Make parabox `eater`, moving along `dir`, swallow `occupant` at `at`: the occupant moves
+    // back against `dir` into the eater through the side facing it.
+    fn eat(&mut self, occupant: Square, at: Location, eater: i32, dir: (i32, i32), trail: &mut Trail, report: &mut MoveReport) -> Result<(), MoveError> {
+        let eaten = SquareMove {
+            square: occupant,
+            from: at,
+            // Set to the entry cell it lands in
+            to: at,
+            exited: Vec::new(),
+            entered: vec![eater],
+        };
+        self.move_into_box(eaten, eater, (-dir.0, -dir.1), Offset::CENTRE, trail, report)
+    }
+
+    // Carry out `square_move` into parabox `id`, entered along `dir` at `offset` across, at the
+    // first of its entry cells that has room.
+    fn move_into_box(&mut self, square_move: SquareMove, id: i32, dir: (i32, i32), offset: Offset, trail: &mut Trail, report: &mut MoveReport) -> Result<(), MoveError> {
+        let (cells, inner_dir) = self.paraboxes[id as usize].entry_cells(dir, offset);
+        let mut first_error = None;
+        for ((x, y), offset) in cells {
+            let mut entering = square_move.clone();
+            entering.to = (id, (x + inner_dir.0, y + inner_dir.1));
+            match self.attempt(report, |level, report| level.move_into(entering, inner_dir, offset, trail, report)) {
+                Ok(()) => return Ok(()),
+                Err(error @ (MoveError::RecursionLimit | MoveError::EndlessEntry { .. })) => return Err(error),
+                Err(error) => {
+                    first_error.get_or_insert(error);
+                }
+            }
+        }
+        Err(first_error.expect("every side of a box has a cell"))
+    }
+
+    // Run `f` on a copy of the level and report, and keep the result only if it succeeds.
+    fn attempt(
+        &mut self,
+        report: &mut MoveReport,
+        f: impl FnOnce(&mut LevelConfig, &mut MoveReport) -> Result<(), MoveError>,
+    ) -> Result<(), MoveError> {
+        let mut trial = self.clone();
+        let mut trial_report = report.clone();
+        f(&mut trial, &mut trial_report)?;
+        *self = trial;
+        *report = trial_report;
+        Ok(())
+    }
+
+    // The cell one step along `dir` from `from`, walking out through `outer` boxes at the edges.
+    // Also returns the ids of the boxes exited on the way, innermost first, the direction the
+    // square arrives in, which leaving a flipped box mirrors, and how far across the cell the
+    // square arrives: exiting a box maps its position along the side onto the single cell the
+    // box takes up outside.
+    // A box with nothing outside it, or left a second time because the way out goes round in
+    // circles, is left through its infinity box instead.
+    fn neighbour(&self, from: Location, dir: (i32, i32)) -> Result<Arrival, MoveError> {
+        let (mut box_id, (x, y)) = from;
+        let mut dir = dir;
+        let mut pos = (x + dir.0, y + dir.1);
+        let mut exited = Vec::new();
+        let mut offset = Offset::CENTRE;
+        let mut through_infinity = Vec::new();
+        while !self.paraboxes[box_id as usize].check_inbounds(pos) {
+            let parabox = &self.paraboxes[box_id as usize];
+            if !self.rules.allow_exit {
+                return Err(match parabox.outer {
+                    Some(_) => MoveError::Forbidden { rule: "leaving boxes" },
+                    None => MoveError::NoOuterBox { box_id },
+                });
+            }
+            offset = offset.exit(across(pos, dir), across(parabox.size, dir));
+            if parabox.flipped {
+                (dir, offset) = mirror(dir, offset);
+            }
+            let endless = exited.contains(&box_id);
+            let (outer_id, (x, y)) = match parabox.outer {
+                Some(outer_id) if !endless => {
+                    (outer_id, self.paraboxes[outer_id as usize].find_box(box_id).ok_or(MoveError::NoOuterBox { box_id })?)
+                }
+                _ if !through_infinity.contains(&box_id) => {
+                    through_infinity.push(box_id);
+                    match self.find_infinity(box_id) {
+                        Some(location) => location,
+                        None if endless => return Err(MoveError::EndlessExit { box_id }),
+                        None => return Err(MoveError::NoOuterBox { box_id }),
+                    }
+                }
+                _ => return Err(MoveError::EndlessExit { box_id }),
+            };
+            pos = (x + dir.0, y + dir.1);
+            exited.push(box_id);
+            box_id = outer_id;
+        }
+        Ok(((box_id, pos), exited, dir, offset))
+    }
+
+    // Check that every box's `outer` is the box its `Parabox` cell is in, and that boxes without
+    // a `Parabox` cell have none, so that walking out of a box always leads where it is drawn.
+    fn check_outers(&self) -> Result<(), LevelLoadError> {
+        for parabox in &self.paraboxes {
+            let placed_in = self.paraboxes.iter().find(|outer| outer.find_box(parabox.id).is_some()).map(|outer| outer.id);
+            if parabox.outer != placed_in {
+                let expected = match placed_in {
+                    Some(outer) => format!("{}, the box holding its Parabox cell", outer),
+                    None => "null, as no box holds its Parabox cell".to_string(),
+                };
+                return Err(LevelLoadError::invalid(&format!("paraboxes[{}].outer", parabox.id), expected));
+            }
+        }
+        Ok(())
+    }
+
+    // Where an infinity box of parabox `id` sits, if the level has one.
+    fn find_infinity(&self, id: i32) -> Option<Location> {
+        self.paraboxes.iter().find_map(|parabox| {
+            let (&pos, _) = parabox.map.iter().find(|(_, square)| **square == Square::Infinity(id))?;
+            Some((parabox.id, pos))
+        })
+    }
+
+    // The parabox entered in place of parabox `id` when entering it goes on forever, if any.
+    fn epsilon_box(&self, id: i32) -> Option<i32> {
+        self.paraboxes.iter().find(|parabox| parabox.epsilon_of == Some(id)).map(|parabox| parabox.id)
+    }
+
+    // Apply `square_move`, whose destination must already be empty.
+    fn place(&mut self, square_move: &SquareMove) {
+        let SquareMove { square, from, to, .. } = square_move;
+        self.paraboxes[from.0 as usize].remove_square(from.1);
+        if let Square::Parabox(id) = square {
+            self.paraboxes[*id as usize].set_outer(Some(to.0));
+        }
+        if self.is_player(square)
+            && let Some(player) = self.players.iter_mut().find(|player| **player == *from)
+        {
+            *player = *to;
+        }
+        self.paraboxes[to.0 as usize].add_square(to.1, square.clone());
+    }
+
+    // The number files give the player at `location`, if they have to number the players as
+    // listing them box by box, the boxes that are players last, would count them differently.
+    fn player_number(&self, location: Location) -> Option<usize> {
+        let listed = |&(box_id, (x, y)): &Location| match self.paraboxes[box_id as usize].find_at(x, y) {
+            Some(Square::Parabox(id)) => (1, *id, (0, 0)),
+            _ => (0, box_id, (x, y)),
+        };
+        if self.players.is_sorted_by_key(listed) {
+            return None;
+        }
+        self.players.iter().position(|&player| player == location)
+    }
+
+    // Whether `square` is a player: a player square, or the placement of a parabox that is a
+    // player.
+    fn is_player(&self, square: &Square) -> bool {
+        match square {
+            Square::Player => true,
+            Square::Parabox(id) => self.paraboxes[*id as usize].player,
+            _ => false,
+        }
+    }
+
+    // Every target (including player targets) that is currently occupied, as (box_id, (x, y)).
+    fn filled_targets(&self) -> Vec<(i32, (i32, i32))> {
+        let mut filled: Vec<(i32, (i32, i32))> = self.paraboxes
+            .iter()
+            .flat_map(|parabox| parabox.filled_targets().into_iter().map(|pos| (parabox.id, pos)))
+            .collect();
+        for parabox in &self.paraboxes {
+            for &pos in &parabox.player_targets {
+                if self.players.contains(&(parabox.id, pos)) {
+                    filled.push((parabox.id, pos));
+                }
+            }
+            for &(pos, target) in &parabox.typed_targets {
+                if self.fills(parabox, pos, target) {
+                    filled.push((parabox.id, pos));
+                }
+            }
+        }
+        filled
+    }
+
+    // Whether the cell at `pos` in `parabox` holds what `target` needs.
+    fn fills(&self, parabox: &Parabox, pos: (i32, i32), target: Target) -> bool {
+        match (target, parabox.find_at(pos.0, pos.1)) {
+            (Target::Block, Some(Square::Block)) => true,
+            (Target::Parabox { id }, Some(Square::Parabox(other) | Square::Clone(other) | Square::Infinity(other))) => *other == id,
+            (Target::Player { number }, _) => self.players.get(number) == Some(&(parabox.id, pos)),
+            _ => false,
+        }
+    }
+
+    /// Whether every win condition of the level holds. Conditions other than move limits are
+    /// the level's objectives; a level without any has to fill every target.
+    pub fn check_win(&self) -> bool {
+        let has_objective = self.win_conditions.iter().any(|condition| !matches!(condition, WinCondition::WithinMoves { .. }));
+        (has_objective || self.targets_filled()) && self.win_conditions.iter().all(|condition| self.holds(condition))
+    }
+
+    /// Whether more moves have been made than a move limit of the level allows.
+    pub fn out_of_moves(&self) -> bool {
+        self.win_conditions.iter().any(|condition| matches!(condition, WinCondition::WithinMoves { .. }) && !self.holds(condition))
+    }
+
+    fn holds(&self, condition: &WinCondition) -> bool {
+        match *condition {
+            WinCondition::Targets => self.targets_filled(),
+            WinCondition::Reach { id, pos } => self.players.contains(&(id, pos)),
+            WinCondition::Inside { id, outer } => self.paraboxes[id as usize].outer == Some(outer),
+            WinCondition::Empty { id } => self.paraboxes[id as usize].map.values().all(|square| *square == Square::Wall),
+            WinCondition::WithinMoves { moves } => self.moves <= moves,
+        }
+    }
+
+    fn targets_filled(&self) -> bool {
+        for parabox in &self.paraboxes {
+            if !parabox.check_win() {
+                return false;
+            }
+            if parabox.player_targets.iter().any(|&pos| !self.players.contains(&(parabox.id, pos))) {
+                return false;
+            }
+            if parabox.typed_targets.iter().any(|&(pos, target)| !self.fills(parabox, pos, target)) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl Debug for LevelConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "LevelConfig {{ level: {}, players: {:?}, paraboxes: {:?} }}", self.level, self.players, self.paraboxes)
+    }
+}
+
+/// Why a level file could not be loaded.
+#[derive(Debug)]
+pub enum LevelLoadError {
+    /// The file could not be read or written.
+    Io { file: String, error: std::io::Error },
+    /// The file is not valid JSON.
+    Json { file: String, error: serde_json::Error },
+    /// The field at JSON `path` (e.g. `paraboxes[2].map["(1, 3)"].type`) is missing or wrong.
+    InvalidField { path: String, expected: String },
+    /// The level uses features this game cannot play yet, each prefixed with where it appears.
+    Unsupported { features: Vec<String> },
+}
+
+impl LevelLoadError {
+    fn invalid(path: &str, expected: impl Into<String>) -> Self {
+        LevelLoadError::InvalidField { path: path.to_string(), expected: expected.into() }
+    }
+}
+
+impl Display for LevelLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LevelLoadError::Io { file, error } => write!(f, "{}: {}", file, error),
+            LevelLoadError::Json { file, error } => write!(f, "{}: {}", file, error),
+            LevelLoadError::InvalidField { path, expected } => write!(f, "{}: expected {}", path, expected),
+            LevelLoadError::Unsupported { features } => write!(f, "unsupported: {}", features.join(", ")),
+        }
+    }
+}
+
+/// Why a move was rejected.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MoveError {
+    /// A wall at `pos` in parabox `box_id` stopped the push chain.
+    BlockedByWall { box_id: i32, pos: (i32, i32) },
+    /// Parabox `box_id` could not be pushed, and nothing could enter it through `side`.
+    EntryBlocked { box_id: i32, side: Side },
+    /// Something tried to walk out of parabox `box_id`, which has no `outer` box. The edges of
+    /// the outermost box stop moves like walls.
+    NoOuterBox { box_id: i32 },
+    /// Walking out of parabox `box_id` leads back out of it forever, as when a box inside
+    /// itself is pushed over its own edge, and it has no infinity box to come out of.
+    EndlessExit { box_id: i32 },
+    /// Entering parabox `box_id` leads back into it forever and it has no epsilon box to go
+    /// into instead.
+    EndlessEntry { box_id: i32 },
+    /// A chain of pushes and entries came back to the cell at `pos` in parabox `box_id`,
+    /// moving the same way, so it would never end.
+    Cycle { box_id: i32, pos: (i32, i32) },
+    /// The move needed more pushes and entries than the level's depth limit allows, or tried
+    /// more of them in all than any move may.
+    RecursionLimit,
+    /// A player is recorded at `pos` in parabox `box_id`, but nothing is there.
+    PlayerMissing { box_id: i32, pos: (i32, i32) },
+    /// The level has no player to move, as before one is loaded.
+    NoPlayer,
+    /// The move would push more than `limit` squares in a row, which the level's rules forbid.
+    PushChainTooLong { limit: usize },
+    /// The level's rules forbid the `rule` the move needed, such as "entering boxes".
+    Forbidden { rule: &'static str },
+}
+
+impl Display for MoveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MoveError::BlockedByWall { box_id, pos } => write!(f, "Blocked by a wall at {:?} in box {}", pos, box_id + 1),
+            MoveError::EntryBlocked { box_id, side } => write!(f, "Box {} cannot be entered from the {}", box_id + 1, side),
+            MoveError::NoOuterBox { box_id } => write!(f, "There is nothing outside box {}", box_id + 1),
+            MoveError::EndlessExit { box_id } => write!(f, "Leaving box {} leads out of it forever", box_id + 1),
+            MoveError::EndlessEntry { box_id } => write!(f, "Entering box {} leads into it forever", box_id + 1),
+            MoveError::Cycle { box_id, pos } => write!(f, "Move loops back to {:?} in box {}", pos, box_id + 1),
+            MoveError::RecursionLimit => write!(f, "Move is nested too deeply"),
+            MoveError::PlayerMissing { box_id, pos } => write!(f, "No player at {:?} in box {}", pos, box_id + 1),
+            MoveError::NoPlayer => write!(f, "There is no player to move"),
+            MoveError::PushChainTooLong { limit } => write!(f, "Cannot push more than {} at once here", limit),
+            MoveError::Forbidden { rule } => write!(f, "This level does not allow {}", rule),
+        }
+    }
+}
+
+/// A target that only one kind of square fills, unlike plain targets, which any block or box
+/// fills, and player targets, which any player fills.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "needs")]
+pub enum Target {
+    /// A plain block, not a box.
+    Block,
+    /// Parabox `id`, or any copy of it.
+    Parabox { id: i32 },
+    /// Player `number`, counting players in the order the level file lists them, or by the
+    /// numbers it gives them.
+    Player { number: usize },
+}
+
+/// Which moves a level allows. Levels allow every move but pulling by default; tutorial levels
+/// can play like plain Sokoban instead, with boxes that are only ever pushed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rules {
+    /// Whether squares may walk or be pushed into boxes.
+    pub allow_enter: bool,
+    /// Whether squares may walk or be pushed out over the edge of a box.
+    pub allow_exit: bool,
+    /// Whether a box that cannot push or enter what is in its way may swallow it.
+    pub allow_eat: bool,
+    /// Whether players may pull the square behind them along as they move away from it.
+    pub allow_pull: bool,
+    /// The most squares the player may push along in a row, if limited.
+    pub max_push_chain: Option<usize>,
+}
+
+impl Rules {
+    /// Full Parabox rules.
+    pub const PARABOX: Rules = Rules { allow_enter: true, allow_exit: true, allow_eat: true, allow_pull: false, max_push_chain: None };
+    /// Plain Sokoban: boxes are never entered, left or eaten, and only pushed one at a time.
+    pub const SOKOBAN: Rules = Rules { allow_enter: false, allow_exit: false, allow_eat: false, allow_pull: false, max_push_chain: Some(1) };
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules::PARABOX
+    }
+}
+
+/// Something a level needs for it to be won; a level lists any number of them, which must all
+/// hold at once.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum WinCondition {
+    /// Every target in every box is filled.
+    Targets,
+    /// A player stands at `pos` in box `id`.
+    Reach { id: i32, pos: (i32, i32) },
+    /// Box `id` sits in box `outer`.
+    Inside { id: i32, outer: i32 },
+    /// Box `id` holds nothing but walls.
+    Empty { id: i32 },
+    /// The level is won within `moves` moves. On its own it limits filling every target.
+    WithinMoves { moves: usize },
+}
+
+/// A side of a parabox.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+impl Side {
+    /// The side a square moving along `dir` enters a parabox through.
+    pub fn entered_by(dir: (i32, i32)) -> Self {
+        match dir {
+            (1, 0) => Side::Top,
+            (-1, 0) => Side::Bottom,
+            (0, 1) => Side::Left,
+            (0, -1) => Side::Right,
+            _ => panic!("Invalid direction for entering parabox: {:?}", dir),
+        }
+    }
+}
+
+impl Display for Side {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Side::Top => write!(f, "top"),
+            Side::Bottom => write!(f, "bottom"),
+            Side::Left => write!(f, "left"),
+            Side::Right => write!(f, "right"),
+        }
+    }
+}
+
+/// What a single player move did, for animation, sound, replay and analytics code to consume.
+#[derive(Clone, Default)]
+pub struct MoveReport {
+    pub dir: (i32, i32),
+    /// Every square that moved, in the order they were applied: the front of the push chain first.
+    pub moves: Vec<SquareMove>,
+    /// Targets, as (box_id, (x, y)), that were empty before the move and are filled after it.
+    pub satisfied_targets: Vec<(i32, (i32, i32))>,
+}
+
+impl Display for MoveReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "move {:?}:", self.dir)?;
+        for square_move in &self.moves {
+            write!(f, " {}", square_move)?;
+        }
+        if !self.satisfied_targets.is_empty() {
+            write!(f, "; satisfied targets {:?}", self.satisfied_targets)?;
+        }
+        Ok(())
+    }
+}
+
+/// One square moving from one cell to another, possibly across parabox boundaries.
+#[derive(Clone)]
+pub struct SquareMove {
+    /// The square that moved.
+    pub square: Square,
+    /// (box_id, (x, y)) before the move.
+    pub from: (i32, (i32, i32)),
+    /// (box_id, (x, y)) after the move.
+    pub to: (i32, (i32, i32)),
+    /// Paraboxes the square walked out of, innermost first.
+    pub exited: Vec<i32>,
+    /// Paraboxes the square walked into, outermost first.
+    pub entered: Vec<i32>,
+}
+
+impl Display for SquareMove {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{:?} {:?} -> {:?}", self.square, self.from, self.to)?;
+        if !self.exited.is_empty() {
+            write!(f, ", exited {:?}", self.exited)?;
+        }
+        if !self.entered.is_empty() {
+            write!(f, ", entered {:?}", self.entered)?;
+        }
+        write!(f, "]")
+    }
+}
+
+/// Undo/redo stacks of whole-level snapshots, one entry per successful player move.
+/// Each snapshot is a full `LevelConfig`, so every parabox map, `outer` link and the
+/// player position are restored together, no matter how many boxes the move crossed.
+#[derive(Resource, Default)]
+pub struct MoveHistory {
+    undo: Vec<LevelConfig>,
+    redo: Vec<LevelConfig>,
+}
+
+impl MoveHistory {
+    /// Record the state from before a move. Any redo history is dropped.
+    pub fn record(&mut self, before: LevelConfig) {
+        self.undo.push(before);
+        self.redo.clear();
+    }
+
+    pub fn undo(&mut self, level_config: &mut LevelConfig) -> bool {
+        if let Some(before) = self.undo.pop() {
+            self.redo.push(std::mem::replace(level_config, before));
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn redo(&mut self, level_config: &mut LevelConfig) -> bool {
+        if let Some(after) = self.redo.pop() {
+            self.undo.push(std::mem::replace(level_config, after));
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
+
+// How far across a cell a square is, perpendicular to its move, as the fraction `num / den`
+// of the cell's width. Kept exact so that entries line up with the cells they map onto.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Offset {
+    num: i64,
+    den: i64,
+}
+
+impl Offset {
+    const CENTRE: Offset = Offset { num: 1, den: 2 };
+
+    fn new(num: i64, den: i64) -> Self {
+        let (mut a, mut b) = (num, den);
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        Offset { num: num / a, den: den / a }
+    }
+
+    // The offset across a box `width` cells wide of a square `self` across its cell `i`.
+    fn exit(self, i: i32, width: i32) -> Self {
+        Offset::new(i as i64 * self.den + self.num, self.den * width as i64)
+    }
+
+    // The cell `self` falls in across a box `width` cells wide, and the offset within that cell.
+    fn enter(self, width: i32) -> (i32, Self) {
+        let scaled = self.num * width as i64;
+        let i = scaled / self.den;
+        (i as i32, Offset::new(scaled - i * self.den, self.den))
+    }
+}
+
+// The component of `pos` (or the extent of `size`) perpendicular to a move along `dir`.
+fn across((x, y): (i32, i32), dir: (i32, i32)) -> i32 {
+    if dir.0 == 0 { x } else { y }
+}
+
+// A move along `dir` at `offset` across its cell, as seen left to right mirrored: sideways moves
+// turn round, and up or down moves keep going but are as far across from the other edge.
+fn mirror(dir: (i32, i32), offset: Offset) -> ((i32, i32), Offset) {
+    if dir.0 == 0 {
+        ((dir.0, -dir.1), offset)
+    } else {
+        (dir, Offset { num: offset.den - offset.num, den: offset.den })
+    }
+}
+
+#[derive(Resource, Clone, PartialEq)]
+pub struct Parabox {
+    id: i32,
+    // outer: Option<Box<Parabox>>,
+    outer: Option<i32>,
+    map: HashMap<(i32, i32), Square>,
+    player_targets: Vec<(i32, i32)>, // Cells that must each hold a player to win
+    size: (i32, i32), // (width, height)
+    targets: Vec<(i32, i32)>, // List of target positions
+    typed_targets: Vec<((i32, i32), Target)>, // Targets that need one particular square
+    epsilon_of: Option<i32>, // The box entered through this one when entering it never ends
+    flipped: bool, // Whether the inside is mirrored left to right from how it looks outside
+    player: bool, // Whether this box is the player, moving wherever the player moves
+    locked: bool, // Whether the box stays where it is, to be walked into but never pushed or eaten
+    entry_points: HashMap<Side, i32>, // The cell across each side that squares entering through it try first
+}
+
+impl Parabox {
+    fn new(id: i32, size: (i32, i32)) -> Self {
+        Parabox {
+            id,
+            outer: None,
+            map: HashMap::new(),
+            player_targets: Vec::new(),
+            size,
+            targets: Vec::new(),
+            typed_targets: Vec::new(),
+            epsilon_of: None,
+            flipped: false,
+            player: false,
+            locked: false,
+            entry_points: HashMap::new(),
+        }
+    }
+
+    fn add_square(&mut self, pos: (i32, i32), square: Square) {
+        self.map.insert(pos, square);
+    }
+
+    fn remove_square(&mut self, pos: (i32, i32)) {
+        self.map.remove(&pos);
+    }
+
+    fn add_player_target(&mut self, x: i32, y: i32) {
+        self.player_targets.push((x, y));
+    }
+
+    fn add_target(&mut self, x: i32, y: i32) {
+        self.targets.push((x, y));
+    }
+
+    fn add_typed_target(&mut self, pos: (i32, i32), target: Target) {
+        self.typed_targets.push((pos, target));
+    }
+
+    /// What the target at `pos` needs, if it needs one particular square.
+    pub fn typed_target_at(&self, pos: (i32, i32)) -> Option<Target> {
+        self.typed_targets.iter().find(|(target_pos, _)| *target_pos == pos).map(|&(_, target)| target)
+    }
+
+    pub fn size(&self) -> (i32, i32) {
+        self.size
+    }
+
+    pub fn flipped(&self) -> bool {
+        self.flipped
+    }
+
+    pub fn is_player(&self) -> bool {
+        self.player
+    }
+
+    pub fn locked(&self) -> bool {
+        self.locked
+    }
+
+    pub fn find_at(&self, x: i32, y: i32) -> Option<&Square> {
+        self.map.get(&(x, y))
+    }
+
+    // Where parabox `id` has its canonical placement in this box, if it is here.
+    // A box may sit inside itself.
+    fn find_box(&self, id: i32) -> Option<(i32, i32)> {
+        self.map.iter().find(|(_, square)| **square == Square::Parabox(id)).map(|(&pos, _)| pos)
+    }
+
+    fn check_inbounds(&self, (x, y): (i32, i32)) -> bool {
+        x >= 0 && y >= 0 && x < self.size.0 && y < self.size.1
+    }
+
+    // Whether every box target here is filled. Player targets depend on where the player is,
+    // which only the level knows.
+    fn check_win(&self) -> bool {
+        for (target_x, target_y) in &self.targets {
+            if let Some(Square::Block | Square::Parabox(_) | Square::Clone(_) | Square::Infinity(_)) = self.find_at(*target_x, *target_y) {
+                continue;
+            }
+            else {
+                return false;
+            }
+        }
+        return true;
+    }
+
+    fn filled_targets(&self) -> Vec<(i32, i32)> {
+        self.targets
+            .iter()
+            .filter(|(x, y)| matches!(self.find_at(*x, *y), Some(Square::Block | Square::Parabox(_) | Square::Clone(_) | Square::Infinity(_))))
+            .cloned()
+            .collect()
+    }
+
+    /// The README symbol for the cell at `pos`. A player target takes precedence over a box target.
+    pub fn glyph_at(&self, pos: (i32, i32)) -> String {
+        let is_target = self.targets.contains(&pos);
+        let is_player_target = self.player_targets.contains(&pos);
+        match self.map.get(&pos) {
+            Some(Square::Player) if is_player_target => "P".to_string(),
+            Some(Square::Block) if is_target => "B".to_string(),
+            Some(square) => format!("{:?}", square),
+            None if is_player_target => "=".to_string(),
+            None if is_target => "_".to_string(),
+            None => ".".to_string(),
+        }
+    }
+
+    // Where a square entering along `dir`, `offset` of the way across, can land, best first:
+    // the cell just outside the side next to each landing cell and how far across that cell it
+    // lands, together with the direction it moves in inside. A flipped box is entered as its
+    // mirror image.
+    // The square first tries the side's entry point if the level sets one, or else the cell
+    // `offset` falls in, then the rest of the side, nearest first, arriving at their middles.
+    fn entry_cells(&self, dir: (i32, i32), offset: Offset) -> (Vec<Entry>, (i32, i32)) {
+        let (dir, offset) = if self.flipped { mirror(dir, offset) } else { (dir, offset) };
+        let width = across(self.size, dir);
+        let first = match self.entry_points.get(&Side::entered_by(dir)) {
+            Some(&i) => (i, Offset::CENTRE),
+            None => offset.enter(width),
+        };
+        let mut rest: Vec<i32> = (0..width).filter(|&i| i != first.0).collect();
+        rest.sort_by_key(|&i| ((i - first.0).abs(), i));
+        let cells = std::iter::once(first)
+            .chain(rest.into_iter().map(|i| (i, Offset::CENTRE)))
+            .map(|(i, offset)| (self.outside(dir, i), offset))
+            .collect();
+        (cells, dir)
+    }
+
+    // The cell just outside the side entered along `dir`, next to cell `i` across that side.
+    fn outside(&self, dir: (i32, i32), i: i32) -> (i32, i32) {
+        if let (1, 0) = dir {
+            // Enter from the top
+            (-1, i)
+        }
+        else if let (-1, 0) = dir {
+            // Enter from the bottom
+            (self.size.0, i)
+        }
+        else if let (0, 1) = dir {
+            // Enter from the left
+            (i, -1)
+        }
+        else if let (0, -1) = dir {
+            // Enter from the right
+            (i, self.size.1)
+        }
+        else {
+            panic!("Invalid direction for entering parabox: {:?}", dir);
+        }
+    }
+
+    fn set_outer(&mut self, outer_id: Option<i32>) {
+        self.outer = outer_id;
+    }
+
+    fn set_epsilon_of(&mut self, id: Option<i32>) {
+        self.epsilon_of = id;
+    }
+
+    fn set_flipped(&mut self, flipped: bool) {
+        self.flipped = flipped;
+    }
+
+    fn set_player(&mut self, player: bool) {
+        self.player = player;
+    }
+
+    fn set_locked(&mut self, locked: bool) {
+        self.locked = locked;
+    }
+
+    fn set_entry_point(&mut self, side: Side, i: i32) {
+        self.entry_points.insert(side, i);
+    }
+}
+
+impl Debug for Parabox {
+
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for x in 0..self.size.0 {
+            for y in 0..self.size.1 {
+                write!(f, "{}", self.glyph_at((x, y)))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Resource, Clone, PartialEq, Eq)]
+pub enum Square {
+    Wall,
+    Block,
+    /// The player, which pushes, enters and gets pushed like a block.
+    Player,
+    /// The canonical placement of a parabox: the one its `outer` points at and that squares
+    /// leaving it come out of.
+    Parabox(i32),
+    /// Another placement of the same parabox, which can be entered and pushed like the
+    /// canonical one but is never exited through.
+    Clone(i32),
+    /// A clone that squares come out of when leaving the parabox never ends: when it has no
+    /// `outer`, or its way out goes round in a cycle.
+    Infinity(i32),
+}
+
+impl Debug for Square {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Square::Wall => write!(f, "#"),
+            Square::Block => write!(f, "b"),
+            Square::Player => write!(f, "p"),
+            // Square::Target(true) => write!(f, "="),
+            // Square::Target(false) => write!(f, "_"),
+            Square::Parabox(id) | Square::Clone(id) | Square::Infinity(id) => write!(f, "{}", id + 1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEFT: (i32, i32) = (0, -1);
+
+    // Box 2 sits right of box 3 between walls in box 1, so walking left out of box 2 runs into
+    // box 3 and enters it.
+    fn exit_into_a_taller_box(player_row: usize) -> LevelConfig {
+        let mut inner = ["..."; 3];
+        inner[player_row] = "p..";
+        let text = format!("box 1\n####\n#32#\n####\n\nbox 2 in 1\n{}\n\nbox 3 in 1\n..\n..\n..\n..\n..\n..\n", inner.join("\n"));
+        LevelConfig::from_ascii(1, &text).unwrap()
+    }
+
+    #[test]
+    fn entry_from_a_cell_is_at_the_middle_of_the_side() {
+        let mut level = LevelConfig::from_ascii(1, "box 1\n#####\n##2p#\n#####\n\nbox 2 in 1\n....\n....\n....\n....\n").unwrap();
+        level.shift(LEFT).unwrap();
+        assert_eq!(level.players, vec![(1, (2, 3))]);
+    }
+
+    #[test]
+    fn a_box_inside_itself_is_exited_next_to_its_own_cell() {
+        let mut level = LevelConfig::from_ascii(1, "box 1 in 1\np..\n..1\n...\n").unwrap();
+        let report = level.shift((-1, 0)).unwrap();
+        assert_eq!(level.players, vec![(0, (0, 2))]);
+        assert_eq!(report.moves[0].exited, vec![0]);
+    }
+
+    #[test]
+    fn a_box_pushed_over_its_own_edge_is_entered_instead() {
+        let mut level = LevelConfig::from_ascii(1, "box 1 in 1\n...\n.p1\n...\n").unwrap();
+        let report = level.shift((0, 1)).unwrap();
+        assert_eq!(level.players, vec![(0, (1, 0))]);
+        assert_eq!(report.moves[0].entered, vec![0]);
+        assert_eq!(level.paraboxes[0].find_box(0), Some((1, 2)));
+    }
+
+    #[test]
+    fn clones_lead_into_the_shared_box_which_is_left_through_its_exit() {
+        let text = "box 1\n#######\n#2p..2#\n#.....#\n#######\n\nbox 2 in 1\n...\n...\n...\nexit 1 5\n";
+        let mut level = LevelConfig::from_ascii(1, text).unwrap();
+        assert_eq!(level.paraboxes[0].find_at(1, 1), Some(&Square::Clone(1)));
+        level.shift(LEFT).unwrap();
+        assert_eq!(level.players, vec![(1, (1, 2))]);
+        level.shift((1, 0)).unwrap();
+        level.shift((1, 0)).unwrap();
+        assert_eq!(level.players, vec![(0, (2, 5))]);
+    }
+
+    #[test]
+    fn a_box_pushed_out_of_itself_comes_out_of_its_infinity_box() {
+        let text = "box 1 in 1\n...\n.p1\n...\n\nbox 2\n#####\n#.1.#\n#####\ninfinity 1 2\n";
+        let mut level = LevelConfig::from_ascii(1, text).unwrap();
+        let report = level.shift((0, 1)).unwrap();
+        assert_eq!(report.moves[0].exited, vec![0, 0]);
+        assert_eq!(level.paraboxes[1].find_box(0), Some((1, 3)));
+        assert_eq!(level.paraboxes[0].outer, Some(1));
+        assert_eq!(level.players, vec![(0, (1, 2))]);
+    }
+
+    #[test]
+    fn leaving_a_box_with_nothing_outside_goes_through_its_infinity_box() {
+        let text = "box 1\np..\n\nbox 2\n#.#\n#1#\n###\ninfinity 1 1\n";
+        let mut level = LevelConfig::from_ascii(1, text).unwrap();
+        level.shift((-1, 0)).unwrap();
+        assert_eq!(level.players, vec![(1, (0, 1))]);
+    }
+
+    // Entering box 2 runs into a clone of itself, walled in, so entering goes on forever
+    const ENDLESS_ENTRY: &str = "box 1\n#####\n#p2##\n#####\n\nbox 2 in 1\n###\n2#3\n###\n\nbox 3 in 2\n...\n...\n...\n";
+
+    #[test]
+    fn endless_entries_lead_into_the_epsilon_box() {
+        let mut level = LevelConfig::from_ascii(1, &format!("{}epsilon_of 2\n", ENDLESS_ENTRY)).unwrap();
+        let report = level.shift((0, 1)).unwrap();
+        assert_eq!(report.moves[0].entered, vec![1, 2]);
+        assert_eq!(level.players, vec![(2, (1, 0))]);
+    }
+
+    #[test]
+    fn endless_entries_without_an_epsilon_box_are_rejected() {
+        let mut level = LevelConfig::from_ascii(1, ENDLESS_ENTRY).unwrap();
+        assert_eq!(level.shift((0, 1)).err(), Some(MoveError::EndlessEntry { box_id: 1 }));
+    }
+
+    #[test]
+    fn a_box_eats_a_block_it_cannot_push() {
+        let mut level = LevelConfig::from_ascii(1, "box 1\n#####\n#p2b#\n#####\n\nbox 2 in 1\n...\n...\n...\n").unwrap();
+        let report = level.shift((0, 1)).unwrap();
+        assert_eq!(report.moves[0].to, (1, (1, 2)));
+        assert_eq!(level.paraboxes[1].find_at(1, 2), Some(&Square::Block));
+        assert_eq!(level.paraboxes[0].find_box(1), Some((1, 3)));
+        assert_eq!(level.players, vec![(0, (1, 2))]);
+    }
+
+    #[test]
+    fn a_box_eats_a_parabox_it_can_neither_push_nor_enter() {
+        let mut level = LevelConfig::from_ascii(1, "box 1\n#####\n#p23#\n#####\n\nbox 2 in 1\n...\n...\n...\n\nbox 3 in 1\n#\n").unwrap();
+        level.shift((0, 1)).unwrap();
+        assert_eq!(level.paraboxes[1].find_box(2), Some((1, 2)));
+        assert_eq!(level.paraboxes[2].outer, Some(1));
+    }
+
+    #[test]
+    fn only_paraboxes_eat() {
+        let mut level = LevelConfig::from_ascii(1, "box 1\n#####\n#pb2#\n#####\n\nbox 2 in 1\n#\n").unwrap();
+        assert_eq!(level.shift((0, 1)).err(), Some(MoveError::EntryBlocked { box_id: 1, side: Side::Left }));
+    }
+
+    #[test]
+    fn entry_after_an_exit_keeps_the_position_along_the_side() {
+        for (row, entered_row) in [(0, 1), (1, 3), (2, 5)] {
+            let mut level = exit_into_a_taller_box(row);
+            let report = level.shift(LEFT).unwrap();
+            assert_eq!(level.players, vec![(2, (entered_row, 1))], "{}", report);
+        }
+    }
+
+    #[test]
+    fn exit_then_entry_maps_back_onto_the_same_row() {
+        let mut level = exit_into_a_taller_box(2);
+        level.shift(LEFT).unwrap();
+        level.shift((0, 1)).unwrap();
+        // Leaving box 3 from row 5 of 6 enters the 3 row box 2 at row 2
+        assert_eq!(level.players, vec![(1, (2, 0))]);
+    }
+
+    #[test]
+    fn a_flipped_box_is_entered_on_the_far_side_and_left_the_way_it_was_entered() {
+        let mut level = LevelConfig::from_ascii(1, "box 1\n####\n#p2#\n####\n\nbox 2 in 1\n...\n...\n...\nflipped\n").unwrap();
+        level.shift((0, 1)).unwrap();
+        assert_eq!(level.players, vec![(1, (1, 2))]);
+        level.shift((0, 1)).unwrap();
+        assert_eq!(level.players, vec![(0, (1, 1))]);
+    }
+
+    #[test]
+    fn moving_up_or_down_through_a_flipped_box_mirrors_the_column() {
+        let text = "box 1\n#####\n##3##\n##2##\n#####\n\nbox 2 in 1\np..\n...\n...\n\nbox 3 in 1\n...\n...\n...\nflipped\n";
+        let mut level = LevelConfig::from_ascii(1, text).unwrap();
+        level.shift((-1, 0)).unwrap();
+        assert_eq!(level.players, vec![(2, (2, 2))]);
+    }
+
+    #[test]
+    fn a_player_box_eats_what_it_cannot_push() {
+        let mut level = LevelConfig::from_ascii(1, "box 1\n####\n#2b#\n####\n\nbox 2 in 1\n...\n...\n...\nplayer\n").unwrap();
+        level.shift((0, 1)).unwrap();
+        assert_eq!(level.players, vec![(0, (1, 2))]);
+        assert_eq!(level.paraboxes[1].find_at(1, 2), Some(&Square::Block));
+    }
+
+    #[test]
+    fn players_in_a_line_move_together() {
+        let mut level = LevelConfig::from_ascii(1, "box 1\n######\n#pp..#\n######\n").unwrap();
+        level.shift((0, 1)).unwrap();
+        assert_eq!(level.players, vec![(0, (1, 2)), (0, (1, 3))]);
+    }
+
+    #[test]
+    fn blocked_players_stay_while_the_others_move() {
+        let mut level = LevelConfig::from_ascii(1, "box 1\n#####\n#p..#\n#p#.#\n#####\n").unwrap();
+        level.shift((0, 1)).unwrap();
+        assert_eq!(level.players, vec![(0, (1, 2)), (0, (2, 1))]);
+        let mut stuck = LevelConfig::from_ascii(1, "box 1\n####\n#pp#\n####\n").unwrap();
+        assert_eq!(stuck.shift((0, 1)).err(), Some(MoveError::BlockedByWall { box_id: 0, pos: (1, 3) }));
+    }
+
+    #[test]
+    fn every_player_target_needs_a_player() {
+        let mut level = LevelConfig::from_ascii(1, "box 1\n#####\n#=p.#\n#=.p#\n#####\n").unwrap();
+        level.shift(LEFT).unwrap();
+        assert!(!level.check_win());
+        level.shift(LEFT).unwrap();
+        assert!(level.check_win());
+    }
+
+    #[test]
+    fn typed_targets_need_the_square_they_name() {
+        let targets = "target 1 1 box 2\ntarget 1 2 block\n\nbox 2 in 1\n#\n";
+        let swapped = LevelConfig::from_ascii(1, &format!("box 1\n#####\n#b2p#\n#####\n{}", targets)).unwrap();
+        assert!(!swapped.check_win());
+        assert!(swapped.filled_targets().is_empty());
+        let level = LevelConfig::from_ascii(1, &format!("box 1\n#####\n#2bp#\n#####\n{}", targets)).unwrap();
+        assert!(level.check_win());
+    }
+
+    #[test]
+    fn a_numbered_player_target_needs_that_player() {
+        for (number, win) in [(1, true), (2, false)] {
+            let text = format!("box 1\n######\n#.p.p#\n######\nplayer_target 1 1 {}\n", number);
+            let mut level = LevelConfig::from_ascii(1, &text).unwrap();
+            level.shift(LEFT).unwrap();
+            assert_eq!(level.check_win(), win);
+        }
+    }
+
+    #[test]
+    fn win_conditions_replace_filling_the_targets() {
+        let mut level = LevelConfig::from_ascii(1, "box 1\n####\n#2p#\n####\n\nbox 2 in 1\n#.\n").unwrap();
+        level.win_conditions = vec![WinCondition::Reach { id: 1, pos: (0, 1) }];
+        assert!(!level.check_win());
+        level.shift(LEFT).unwrap();
+        assert!(level.check_win());
+        level.win_conditions = vec![WinCondition::Empty { id: 1 }];
+        assert!(!level.check_win());
+        level.win_conditions = vec![WinCondition::Inside { id: 1, outer: 0 }];
+        assert!(level.check_win());
+    }
+
+    #[test]
+    fn a_move_limit_alone_limits_filling_the_targets() {
+        let mut level = LevelConfig::from_ascii(1, "box 1\n######\n#_b.p#\n######\n").unwrap();
+        level.win_conditions = vec![WinCondition::WithinMoves { moves: 1 }];
+        level.shift(LEFT).unwrap();
+        assert!(!level.check_win());
+        level.shift(LEFT).unwrap();
+        assert!(!level.check_win());
+        assert!(level.out_of_moves());
+        level.win_conditions = vec![WinCondition::WithinMoves { moves: 2 }];
+        assert!(level.check_win());
+    }
+
+    #[test]
+    fn sokoban_rules_push_one_box_and_never_enter_or_leave_one() {
+        let mut level = LevelConfig::from_ascii(1, "box 1\n#####\n#2p.#\n#####\n\nbox 2 in 1\n..\n").unwrap();
+        level.rules = Rules::SOKOBAN;
+        assert_eq!(level.step(LEFT).err(), Some(MoveError::BlockedByWall { box_id: 0, pos: (1, 0) }));
+        let mut level = LevelConfig::from_ascii(1, "box 1\n####\n#.2#\n####\n\nbox 2 in 1\np.\n").unwrap();
+        level.rules = Rules::SOKOBAN;
+        assert_eq!(level.step(LEFT).err(), Some(MoveError::Forbidden { rule: "leaving boxes" }));
+        let mut level = LevelConfig::from_ascii(1, "box 1\n######\n#.bbp#\n######\n").unwrap();
+        level.rules = Rules::SOKOBAN;
+        assert_eq!(level.step(LEFT).err(), Some(MoveError::PushChainTooLong { limit: 1 }));
+        level.rules.max_push_chain = Some(2);
+        assert!(level.step(LEFT).is_ok());
+    }
+
+    #[test]
+    fn pulling_drags_the_square_behind_the_player() {
+        let mut level = LevelConfig::from_ascii(1, "box 1\n#####\n#bp.#\n#####\n").unwrap();
+        assert_eq!(level.pull((0, 1)).err(), Some(MoveError::Forbidden { rule: "pulling" }));
+        level.rules.allow_pull = true;
+        level.pull((0, 1)).unwrap();
+        assert_eq!(level.paraboxes[0].find_at(1, 2), Some(&Square::Block));
+        assert_eq!(level.players, vec![(0, (1, 3))]);
+    }
+
+    #[test]
+    fn pulling_from_the_edge_of_a_box_drags_a_box_into_it() {
+        let mut level = LevelConfig::from_ascii(1, "box 1\n#####\n#32.#\n#####\n\nbox 2 in 1\np.\n\nbox 3 in 1\n.\n").unwrap();
+        level.rules.allow_pull = true;
+        let report = level.pull((0, 1)).unwrap();
+        assert_eq!(level.paraboxes[1].find_box(2), Some((0, 0)));
+        assert_eq!(level.paraboxes[2].outer, Some(1));
+        assert_eq!(report.moves[1].entered, vec![1]);
+    }
+
+    #[test]
+    fn a_locked_box_is_entered_instead_of_pushed() {
+        let mut level = LevelConfig::from_ascii(1, "box 1\n#####\n#p2.#\n#####\n\nbox 2 in 1\n...\n...\n...\nlocked\n").unwrap();
+        level.shift((0, 1)).unwrap();
+        assert_eq!(level.players, vec![(1, (1, 0))]);
+        assert_eq!(level.paraboxes[0].find_box(1), Some((1, 2)));
+    }
+
+    #[test]
+    fn a_locked_box_is_never_eaten() {
+        let text = "box 1\n#####\n#p32#\n#####\n\nbox 2 in 1\n#\nlocked\n\nbox 3 in 1\n#.\n";
+        let mut level = LevelConfig::from_ascii(1, text).unwrap();
+        assert_eq!(level.shift((0, 1)).err(), Some(MoveError::EntryBlocked { box_id: 2, side: Side::Left }));
+        level.paraboxes[1].set_locked(false);
+        level.shift((0, 1)).unwrap();
+        assert_eq!(level.paraboxes[2].find_box(1), Some((0, 1)));
+    }
+
+    #[test]
+    fn a_blocked_entry_falls_back_to_the_nearest_open_cell_of_the_side() {
+        let mut level = LevelConfig::from_ascii(1, "box 1\n####\n#p2#\n####\n\nbox 2 in 1\n#..\n#..\n...\n").unwrap();
+        level.shift((0, 1)).unwrap();
+        assert_eq!(level.players, vec![(1, (2, 0))]);
+    }
+
+    #[test]
+    fn an_entry_point_is_tried_before_the_middle_of_the_side() {
+        let text = "box 1\n####\n#p2#\n####\n\nbox 2 in 1\n...\n...\n...\nentry left 0\n";
+        let mut level = LevelConfig::from_ascii(1, text).unwrap();
+        assert_eq!(level.to_ascii().unwrap(), text);
+        assert_eq!(LevelFile::parse("entry.json", &level.to_json()).unwrap().to_level_config(1).unwrap(), level);
+        level.shift((0, 1)).unwrap();
+        assert_eq!(level.players, vec![(1, (0, 0))]);
+    }
+
+    #[test]
+    fn walking_off_the_outermost_box_is_rejected_like_a_wall() {
+        let mut level = LevelConfig::from_ascii(1, "box 1\np.\n").unwrap();
+        let before = level.clone();
+        assert_eq!(level.shift((-1, 0)).err(), Some(MoveError::NoOuterBox { box_id: 0 }));
+        assert_eq!(level, before);
+    }
+
+    #[test]
+    fn push_chains_stop_at_the_level_move_depth() {
+        let mut level = LevelConfig::from_ascii(1, "box 1\n######\n#pbb.#\n######\n").unwrap();
+        level.max_move_depth = Some(2);
+        assert_eq!(level.step((0, 1)).err(), Some(MoveError::RecursionLimit));
+        level.max_move_depth = Some(3);
+        assert!(level.step((0, 1)).is_ok());
+    }
+
+    #[test]
+    fn entries_that_keep_falling_back_stop_at_the_attempt_limit() {
+        // Every cell of the right side of boxes 2 to 8 holds the next box against a wall, and
+        // box 9 is solid, so each entry tries all five cells of the next box's side.
+        let mut text = "box 1\n####\n#2p#\n####\n".to_string();
+        for id in 2..=8 {
+            text += &format!("\nbox {} in {}\n", id, id - 1);
+            text += &format!("####{}\n", id + 1).repeat(5);
+        }
+        text += "\nbox 9 in 8\n#####\n#####\n#####\n#####\n#####\n";
+        let level = LevelConfig::from_ascii(1, &text).unwrap();
+        assert_eq!(level.step((0, -1)).err(), Some(MoveError::RecursionLimit));
+    }
+
+    #[test]
+    fn moving_onto_a_cell_already_on_the_trail_is_a_cycle() {
+        let mut level = LevelConfig::from_ascii(1, "box 1\n#####\n#pb.#\n#####\n").unwrap();
+        let square_move = SquareMove { square: Square::Block, from: (0, (1, 2)), to: (0, (1, 3)), exited: Vec::new(), entered: Vec::new() };
+        let mut trail = Trail { visits: vec![((0, (1, 3)), (0, 1), Offset::CENTRE)], ..Trail::default() };
+        let result = level.move_into(square_move, (0, 1), Offset::CENTRE, &mut trail, &mut MoveReport::default());
+        assert_eq!(result, Err(MoveError::Cycle { box_id: 0, pos: (1, 3) }));
+    }
+
+    // Make a move the way the game does, recording the level from before it.
+    fn play(level: &mut LevelConfig, history: &mut MoveHistory, dir: (i32, i32)) {
+        let before = level.clone();
+        level.shift(dir).unwrap();
+        history.record(before);
+    }
+
+    #[test]
+    fn undo_and_redo_walk_the_recorded_moves() {
+        let start = LevelConfig::from_ascii(1, "box 1\n...p\n").unwrap();
+        let mut level = start.clone();
+        let mut history = MoveHistory::default();
+        play(&mut level, &mut history, LEFT);
+        let after_one = level.clone();
+        play(&mut level, &mut history, LEFT);
+        let after_two = level.clone();
+
+        assert!(history.undo(&mut level));
+        assert!(level == after_one);
+        assert!(history.undo(&mut level));
+        assert!(level == start);
+        assert!(!history.undo(&mut level));
+        assert!(level == start);
+
+        assert!(history.redo(&mut level));
+        assert!(level == after_one);
+        assert!(history.redo(&mut level));
+        assert!(level == after_two);
+        assert!(!history.redo(&mut level));
+        assert!(level == after_two);
+    }
+
+    #[test]
+    fn a_new_move_after_undo_drops_the_redo_history() {
+        let mut level = LevelConfig::from_ascii(1, "box 1\n...p\n").unwrap();
+        let mut history = MoveHistory::default();
+        play(&mut level, &mut history, LEFT);
+        play(&mut level, &mut history, LEFT);
+        history.undo(&mut level);
+        play(&mut level, &mut history, (0, 1));
+        let moved = level.clone();
+
+        assert!(!history.redo(&mut level));
+        assert!(level == moved);
+        assert!(history.undo(&mut level));
+        assert_eq!(level.players, vec![(0, (0, 2))]);
+    }
+
+    #[test]
+    fn a_chain_that_fails_partway_leaves_the_level_unchanged() {
+        // Pushing box 4 left tries to push box 3, then to enter it and push box 5 inside it
+        // against a wall, and then every other way in before giving up
+        let text = "box 1\n######\n#234p#\n######\n\nbox 2 in 1\n##\n##\n\nbox 3 in 1\n#5\n\n\
+            box 4 in 1\n##\n##\n\nbox 5 in 3\n##\n##\n";
+        let mut level = LevelConfig::from_ascii(1, text).unwrap();
+        let before = level.clone();
+        assert!(level.step(LEFT).is_err());
+        assert!(matches!(level.shift(LEFT), Err(MoveError::EntryBlocked { .. })));
+        assert!(level == before);
+    }
+
+    #[test]
+    fn a_player_pushed_by_another_from_a_different_box_does_not_move_again() {
+        // The player in box 2 goes first, as it is further right, walks out of box 2 and
+        // pushes the other player one cell along, which is that player's move
+        let text = "box 1\n#######\n#2p...#\n#######\n\nbox 2 in 1\n....p\n";
+        let mut level = LevelConfig::from_ascii(1, text).unwrap();
+        let report = level.shift((0, 1)).unwrap();
+        assert_eq!(level.players, vec![(0, (1, 3)), (0, (1, 2))]);
+        assert_eq!(report.moves.len(), 2);
+    }
+
+    #[test]
+    fn a_level_without_players_rejects_moves() {
+        let level = LevelConfig::default();
+        assert!(matches!(level.step(LEFT), Err(MoveError::NoPlayer)));
+    }
+}
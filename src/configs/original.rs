@@ -0,0 +1,406 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use super::{LevelConfig, LevelLoadError};
+use super::level_file::{Cell, CellContent, LevelFile, ParaboxFile, LEVEL_FORMAT_VERSION};
+
+// The version written by the original game's editor
+const ORIGINAL_FORMAT_VERSION: i32 = 4;
+
+// Field positions shared by the original `Block` and `Ref` lines
+const BLOCK_FILL_WITH_WALLS: usize = 9;
+const BLOCK_PLAYER: usize = 10;
+const BLOCK_POSSESSABLE: usize = 11;
+const BLOCK_PLAYER_ORDER: usize = 12;
+const BLOCK_FLIP: usize = 13;
+const BLOCK_FLOAT_IN_SPACE: usize = 14;
+const REF_EXIT_BLOCK: usize = 3;
+const REF_INF_EXIT: usize = 4;
+const REF_INF_EXIT_NUM: usize = 5;
+const REF_INF_ENTER: usize = 6;
+const REF_INF_ENTER_NUM: usize = 7;
+const REF_INF_ENTER_ID: usize = 8;
+const REF_PLAYER: usize = 9;
+const REF_POSSESSABLE: usize = 10;
+const REF_PLAYER_ORDER: usize = 11;
+const REF_FLIP: usize = 12;
+const REF_FLOAT_IN_SPACE: usize = 13;
+// Field positions of the original `Wall` line
+const WALL_PLAYER: usize = 2;
+const WALL_POSSESSABLE: usize = 3;
+const WALL_PLAYER_ORDER: usize = 4;
+
+// One object line of the original format, e.g. `Block 3 4 1 5 5 0.6 0.8 1 1 0 0 0 0 0 0 0`
+struct Entry<'a> {
+    line: usize,
+    kind: &'a str,
+    fields: Vec<&'a str>,
+    // Index of the enclosing `Block` entry, from the tab indentation
+    parent: Option<usize>,
+}
+
+impl Entry<'_> {
+    fn path(&self) -> String {
+        format!("line {}", self.line)
+    }
+
+    fn int(&self, index: usize) -> Result<i32, LevelLoadError> {
+        let field = self.fields.get(index).ok_or_else(|| {
+            LevelLoadError::invalid(&self.path(), format!("at least {} fields after \"{}\"", index + 1, self.kind))
+        })?;
+        field.parse().map_err(|_| LevelLoadError::invalid(&self.path(), format!("a number, found {:?}", field)))
+    }
+
+    // Trailing flags were added in later versions of the format and default to off
+    fn flag(&self, index: usize) -> Result<bool, LevelLoadError> {
+        Ok(self.optional_int(index)? != 0)
+    }
+
+    // A trailing number, 0 if the line predates it
+    fn optional_int(&self, index: usize) -> Result<i32, LevelLoadError> {
+        if index < self.fields.len() { self.int(index) } else { Ok(0) }
+    }
+
+    // The original counts `y` up from the bottom row
+    fn pos_in(&self, size: (i32, i32)) -> Result<(i32, i32), LevelLoadError> {
+        Ok((size.0 - 1 - self.int(1)?, self.int(0)?))
+    }
+}
+
+impl LevelConfig {
+    /// Import a level saved by the original game's editor: a header ending in `#`, then one
+    /// `Block`, `Ref`, `Wall` or `Floor` line per object, nested with tabs.
+    /// Anything this game cannot play yet (boxes flipped in only some places, possession, other
+    /// floors, ...) is collected into `LevelLoadError::Unsupported` rather than dropped.
+    pub fn from_original(level: i32, text: &str) -> Result<Self, LevelLoadError> {
+        let mut unsupported = Vec::new();
+        let mut lines = text.lines().enumerate();
+        let mut version = None;
+        for (index, line) in lines.by_ref() {
+            let path = format!("line {}", index + 1);
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words[..] {
+                ["#"] => break,
+                ["version", n] => version = Some(n.parse::<i32>().map_err(|_| LevelLoadError::invalid(&path, "a version number"))?),
+                ["shed", ..] | ["inner_push", ..] => unsupported.push(format!("{}: {} rule", path, words[0])),
+                ["attempt_order", order] if order != "push,enter,eat,possess" => {
+                    unsupported.push(format!("{}: attempt order {}", path, order))
+                }
+                // Music, palettes and draw styles do not change how the level plays
+                _ => {}
+            }
+        }
+        if version != Some(ORIGINAL_FORMAT_VERSION) {
+            return Err(LevelLoadError::invalid("line 1", format!("\"version {}\"", ORIGINAL_FORMAT_VERSION)));
+        }
+
+        let mut entries: Vec<Entry> = Vec::new();
+        // Indices of the `Block` entries enclosing the current line, one per tab
+        let mut enclosing: Vec<usize> = Vec::new();
+        for (index, line) in lines {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let depth = line.len() - line.trim_start_matches('\t').len();
+            if depth > enclosing.len() {
+                return Err(LevelLoadError::invalid(&format!("line {}", index + 1), "a line nested in a Block"));
+            }
+            enclosing.truncate(depth);
+            let parent = enclosing.last().copied();
+            let mut words = line.split_whitespace();
+            let kind = words.next().unwrap();
+            if kind == "Block" {
+                enclosing.push(entries.len());
+            }
+            entries.push(Entry { line: index + 1, kind, fields: words.collect(), parent });
+        }
+
+        // Hollow blocks become paraboxes, numbered in the order of their original ids
+        let mut parabox_entries = Vec::new();
+        for (index, entry) in entries.iter().enumerate() {
+            if entry.kind == "Block" && !entry.flag(BLOCK_FILL_WITH_WALLS)? {
+                parabox_entries.push((entry.int(2)?, index));
+            }
+        }
+        parabox_entries.sort();
+        let mut ids = HashMap::new();
+        let mut parabox_of_entry = HashMap::new();
+        let mut paraboxes = Vec::new();
+        for (id, &(original_id, index)) in parabox_entries.iter().enumerate() {
+            let entry = &entries[index];
+            if ids.insert(original_id, id as i32).is_some() {
+                return Err(LevelLoadError::invalid(&entry.path(), format!("a Block id other than {}", original_id)));
+            }
+            parabox_of_entry.insert(index, id);
+            paraboxes.push(ParaboxFile {
+                size: (entry.int(4)?, entry.int(3)?),
+                flipped: entry.flag(BLOCK_FLIP)?,
+                player: entry.flag(BLOCK_PLAYER)?,
+                ..ParaboxFile::default()
+            });
+        }
+
+        for (index, entry) in entries.iter().enumerate() {
+            let path = entry.path();
+            let Some(parent) = entry.parent else {
+                // Top level boxes sit nowhere until a `Ref` places them
+                if !parabox_of_entry.contains_key(&index) {
+                    return Err(LevelLoadError::invalid(&path, "a hollow Block at the top level"));
+                }
+                continue;
+            };
+            let Some(&outer) = parabox_of_entry.get(&parent) else {
+                unsupported.push(format!("{}: {} inside a solid block", path, entry.kind));
+                continue;
+            };
+            let pos = entry.pos_in(paraboxes[outer].size)?;
+            let place_parabox = |id: i32, paraboxes: &mut Vec<ParaboxFile>| {
+                if paraboxes[id as usize].outer.is_some() {
+                    return Err(LevelLoadError::invalid(&path, format!("one exit Block or Ref per box, box {} has two", id + 1)));
+                }
+                paraboxes[id as usize].outer = Some(outer as i32);
+                paraboxes[outer].cells.push(Cell { pos, content: CellContent::Parabox { id } });
+                Ok(())
+            };
+            match entry.kind {
+                "Block" => {
+                    if entry.flag(BLOCK_FLOAT_IN_SPACE)? {
+                        unsupported.push(format!("{}: box floating in space", path));
+                    }
+                    let player = entry.flag(BLOCK_PLAYER)?;
+                    if entry.optional_int(BLOCK_PLAYER_ORDER)? != 0 {
+                        unsupported.push(format!("{}: player order", path));
+                    }
+                    if player && entry.flag(BLOCK_FILL_WITH_WALLS)? {
+                        paraboxes[outer].cells.push(Cell { pos, content: CellContent::Player { number: None } });
+                    } else if !player && entry.flag(BLOCK_POSSESSABLE)? {
+                        unsupported.push(format!("{}: possessable box", path));
+                    } else if let Some(&id) = parabox_of_entry.get(&index) {
+                        place_parabox(id as i32, &mut paraboxes)?;
+                    } else {
+                        paraboxes[outer].cells.push(Cell { pos, content: CellContent::Block });
+                    }
+                }
+                "Ref" => {
+                    let original_id = entry.int(2)?;
+                    let id = *ids.get(&original_id).ok_or_else(|| {
+                        LevelLoadError::invalid(&format!("{}, field 3", path), "the id of a hollow Block")
+                    })?;
+                    if entry.flag(REF_INF_ENTER)? {
+                        let original_id = entry.int(REF_INF_ENTER_ID)?;
+                        let epsilon_of = *ids.get(&original_id).ok_or_else(|| {
+                            LevelLoadError::invalid(&format!("{}, field {}", path, REF_INF_ENTER_ID + 1), "the id of a hollow Block")
+                        })?;
+                        paraboxes[id as usize].epsilon_of = Some(epsilon_of);
+                    }
+                    if entry.flag(REF_INF_EXIT)? {
+                        paraboxes[outer].cells.push(Cell { pos, content: CellContent::Infinity { id } });
+                    } else if !entry.flag(REF_EXIT_BLOCK)? {
+                        paraboxes[outer].cells.push(Cell { pos, content: CellContent::Clone { id } });
+                    } else {
+                        place_parabox(id, &mut paraboxes)?;
+                    }
+                    // Here a box is flipped wherever it appears
+                    if entry.flag(REF_FLIP)? != paraboxes[id as usize].flipped {
+                        unsupported.push(format!("{}: Ref flipped unlike its Block", path));
+                    }
+                    if entry.flag(REF_PLAYER)? && !paraboxes[id as usize].player {
+                        if entry.flag(REF_EXIT_BLOCK)? {
+                            paraboxes[id as usize].player = true;
+                        } else {
+                            unsupported.push(format!("{}: player that is a clone", path));
+                        }
+                    }
+                    // Only the first level of infinity and epsilon boxes is supported
+                    let inf_exit_num = entry.optional_int(REF_INF_EXIT_NUM)?;
+                    if inf_exit_num != 0 {
+                        unsupported.push(format!("{}: infinity box of level {}", path, inf_exit_num + 1));
+                    }
+                    let inf_enter_num = entry.optional_int(REF_INF_ENTER_NUM)?;
+                    if inf_enter_num != 0 {
+                        unsupported.push(format!("{}: epsilon box of level {}", path, inf_enter_num + 1));
+                    }
+                    if entry.flag(REF_POSSESSABLE)? {
+                        unsupported.push(format!("{}: possessable box", path));
+                    }
+                    if entry.optional_int(REF_PLAYER_ORDER)? != 0 {
+                        unsupported.push(format!("{}: player order", path));
+                    }
+                    if entry.flag(REF_FLOAT_IN_SPACE)? {
+                        unsupported.push(format!("{}: box floating in space", path));
+                    }
+                }
+                "Wall" => {
+                    if entry.flag(WALL_PLAYER)? {
+                        unsupported.push(format!("{}: wall that is a player", path));
+                    }
+                    if entry.flag(WALL_POSSESSABLE)? {
+                        unsupported.push(format!("{}: possessable wall", path));
+                    }
+                    if entry.optional_int(WALL_PLAYER_ORDER)? != 0 {
+                        unsupported.push(format!("{}: player order", path));
+                    }
+                    paraboxes[outer].walls.push(pos);
+                }
+                "Floor" => match entry.fields.get(2).copied() {
+                    Some("Button") => paraboxes[outer].targets.push(pos),
+                    Some("PlayerButton") => paraboxes[outer].player_targets.push(pos),
+                    Some(kind) => unsupported.push(format!("{}: {} floor", path, kind)),
+                    None => return Err(LevelLoadError::invalid(&path, "\"Floor <x> <y> <type>\"")),
+                },
+                kind => {
+                    return Err(LevelLoadError::invalid(&path, format!("Block, Ref, Wall or Floor, found {:?}", kind)));
+                }
+            }
+        }
+        if !unsupported.is_empty() {
+            return Err(LevelLoadError::Unsupported { features: unsupported });
+        }
+        if paraboxes.is_empty() {
+            return Err(LevelLoadError::invalid("line 1", "a hollow Block"));
+        }
+        LevelFile {
+            version: LEVEL_FORMAT_VERSION,
+            metadata: None,
+            max_move_depth: None,
+            rules: None,
+            win_conditions: Vec::new(),
+            paraboxes,
+        }
+        .to_level_config(level)
+    }
+
+    /// Export the level for the original game's editor, in the format read by `from_original`.
+    /// The original has no locked boxes, entry points, targets for one particular square or
+    /// player numbers, so boxes are written without them, and no `max_move_depth`, `rules` or
+    /// `win_conditions`, so levels are written with the defaults.
+    pub fn to_original(&self) -> String {
+        let level_file = LevelFile::from_level_config(self);
+        let mut out = format!("version {}\n#\n", ORIGINAL_FORMAT_VERSION);
+        let mut written = vec![false; level_file.paraboxes.len()];
+        // Boxes sitting nowhere go first so that the root opens the file
+        let mut order: Vec<usize> = (0..level_file.paraboxes.len()).collect();
+        order.sort_by_key(|&id| level_file.paraboxes[id].outer.is_some());
+        for id in order {
+            if !written[id] {
+                write_original_block(&mut out, &level_file.paraboxes, &mut written, id, (-1, -1), 0);
+            }
+        }
+        out
+    }
+}
+
+// Write parabox `id` as a hollow `Block` at original position `pos`, followed by its contents
+// one tab deeper. Boxes already written are placed with a `Ref` instead, as are epsilon boxes,
+// since only a `Ref` can say which box they are the epsilon box of. An epsilon box that sits
+// nowhere therefore loses its link.
+fn write_original_block(out: &mut String, paraboxes: &[ParaboxFile], written: &mut [bool], id: usize, pos: (i32, i32), depth: usize) {
+    let indent = "\t".repeat(depth);
+    let inner_indent = "\t".repeat(depth + 1);
+    let parabox = &paraboxes[id];
+    written[id] = true;
+    writeln!(
+        out,
+        "{}Block {} {} {} {} {} 0.6 0.8 1 1 0 {} {} 0 {} 0 0",
+        indent, pos.0, pos.1, id, parabox.size.1, parabox.size.0, parabox.player as i32, parabox.player as i32, parabox.flipped as i32
+    )
+    .unwrap();
+    // The original counts `y` up from the bottom row
+    let original_pos = |(x, y): (i32, i32)| (y, parabox.size.0 - 1 - x);
+    for &pos in &parabox.walls {
+        let (x, y) = original_pos(pos);
+        writeln!(out, "{}Wall {} {} 0 0 0", inner_indent, x, y).unwrap();
+    }
+    // Solid blocks need ids too; give them one no parabox uses
+    let solid_id = paraboxes.len();
+    for cell in &parabox.cells {
+        let (x, y) = original_pos(cell.pos);
+        match cell.content {
+            CellContent::Block => {
+                writeln!(out, "{}Block {} {} {} 1 1 0.1 0.8 1 1 1 0 0 0 0 0 0", inner_indent, x, y, solid_id).unwrap()
+            }
+            CellContent::Player { .. } => {
+                writeln!(out, "{}Block {} {} {} 1 1 0.9 1 0.7 1 1 1 1 0 0 0 0", inner_indent, x, y, solid_id).unwrap()
+            }
+            CellContent::Parabox { id } if !written[id as usize] && paraboxes[id as usize].epsilon_of.is_none() => {
+                write_original_block(out, paraboxes, written, id as usize, (x, y), depth + 1)
+            }
+            CellContent::Parabox { id } | CellContent::Clone { id } | CellContent::Infinity { id } => {
+                let exit_block = matches!(cell.content, CellContent::Parabox { .. }) as i32;
+                let inf_exit = matches!(cell.content, CellContent::Infinity { .. }) as i32;
+                let (inf_enter, inf_enter_id) = match paraboxes[id as usize].epsilon_of {
+                    Some(epsilon_of) if exit_block == 1 => (1, epsilon_of),
+                    _ => (0, -1),
+                };
+                writeln!(
+                    out,
+                    "{}Ref {} {} {} {} {} 0 {} 0 {} 0 0 0 {} 0 0",
+                    inner_indent, x, y, id, exit_block, inf_exit, inf_enter, inf_enter_id, paraboxes[id as usize].flipped as i32
+                )
+                .unwrap()
+            }
+        }
+    }
+    for &pos in &parabox.targets {
+        let (x, y) = original_pos(pos);
+        writeln!(out, "{}Floor {} {} Button", inner_indent, x, y).unwrap();
+    }
+    for &pos in &parabox.player_targets {
+        let (x, y) = original_pos(pos);
+        writeln!(out, "{}Floor {} {} PlayerButton", inner_indent, x, y).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shipped_levels_round_trip_through_the_original_format() {
+        for level in 1..=12 {
+            let file = format!("{}/assets/levels/{}.json", env!("CARGO_MANIFEST_DIR"), level);
+            let loaded = LevelConfig::try_new(level, &file).unwrap();
+            let reloaded = LevelConfig::from_original(level, &loaded.to_original()).unwrap();
+            assert_eq!(format!("{:?}", loaded), format!("{:?}", reloaded), "level {} changed in a round trip", level);
+        }
+    }
+
+    #[test]
+    fn editor_levels_are_imported_bottom_row_first() {
+        let text = "version 4\n#\n\
+            Block -1 -1 7 3 2 0.6 0.8 1 1 0 0 0 0 0 0 0\n\
+            \tWall 0 0 0 0 0\n\
+            \tBlock 1 1 9 1 1 0.9 1 0.7 1 1 1 1 0 0 0 0\n\
+            \tFloor 2 0 Button\n\
+            \tBlock 2 1 3 1 1 0.1 0.8 1 1 0 0 0 0 0 0 0\n\
+            \t\tFloor 0 0 PlayerButton\n";
+        // Hollow blocks are numbered by their original ids, so block 3 becomes box 1
+        let expected = LevelConfig::from_ascii(1, "box 1 in 2\n=\n\nbox 2\n.p1\n#._\n").unwrap();
+        let imported = LevelConfig::from_original(1, text).unwrap();
+        assert_eq!(imported, expected);
+        assert_eq!(LevelConfig::from_original(1, &imported.to_original()).unwrap(), expected);
+    }
+
+    #[test]
+    fn unsupported_features_are_listed() {
+        let text = "version 4\nshed 1\n#\n\
+            Block -1 -1 0 3 3 0.6 0.8 1 1 0 0 0 0 0 0 0\n\
+            \tBlock 0 0 1 1 1 0.9 1 0.7 1 1 1 1 0 0 0 0\n\
+            \tRef 1 1 0 0 0 0 0 0 -1 0 0 0 0 0 0\n\
+            \tRef 2 1 0 1 1 0 0 0 -1 0 0 0 0 0 0\n\
+            \tRef 2 2 0 1 0 0 0 0 -1 0 0 0 1 0 0\n\
+            \tRef 0 2 0 0 1 3 1 2 0 0 1 0 0 1 0\n\
+            \tBlock 1 0 1 1 1 0.9 1 0.7 1 1 1 1 1 0 0 0\n\
+            \tRef 0 1 0 0 0 0 0 0 -1 0 0 1 0 0 0\n\
+            \tWall 1 2 1 0 0\n\
+            \tWall 2 0 0 1 0\n";
+        let error = LevelConfig::from_original(1, text).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "unsupported: line 2: shed rule, line 8: Ref flipped unlike its Block, \
+            line 9: infinity box of level 4, line 9: epsilon box of level 3, line 9: possessable box, \
+            line 9: box floating in space, line 10: player order, line 11: player order, \
+            line 12: wall that is a player, line 13: possessable wall"
+        );
+    }
+}
@@ -1,8 +1,9 @@
 mod plugins;
 mod configs;
+mod cli;
 
 use bevy::prelude::*;
-use plugins::{menu, game, win};
+use plugins::{menu, game, win, load_error};
 
 const TEXT_COLOR: Color = Color::srgb(0.9, 0.9, 0.9);
 
@@ -10,6 +11,10 @@ const TEXT_COLOR: Color = Color::srgb(0.9, 0.9, 0.9);
 struct Level(i32);
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(code) = cli::run(&args) {
+        std::process::exit(code);
+    }
     // use configs::*;
     // let mut level1 = LevelConfig::new(1, "assets/levels/1.json");
     // let up = (-1, 0);
@@ -25,6 +30,7 @@ fn main() {
             // level_select::level_select_plugin,
             game::game_plugin,
             win::win_plugin,
+            load_error::load_error_plugin,
         ))
         .insert_resource(Level(0))
         .run();
@@ -37,6 +43,7 @@ enum GameState {
     LevelSelect,
     Game,
     Win,
+    LoadError,
 }
 
 fn setup(
@@ -1,23 +1,31 @@
 use bevy::prelude::*;
 use bevy::color::palettes::*;
+use bevy::ecs::system::SystemParam;
 // use bevy::window::WindowClosing;
 
 use crate::plugins::menu;
+use crate::plugins::load_error::LoadErrorMessage;
 use crate::{ Level, GameState, despawn_screen, TEXT_COLOR };
-use crate::configs::LevelConfig;
+use crate::configs::{LevelConfig, MoveHistory, Square, Target};
 
 pub fn game_plugin(app: &mut App) {
     app
         //.init_state::<GameState>()
         .add_systems(OnEnter(GameState::Game), game_setup)
-        .add_systems(FixedUpdate, (
-            game_action, 
+        // Undo and redo act once per key press, so they are read every frame rather than with
+        // the repeating movement timer, and before moves so that each redraws the screen the
+        // one before it left
+        .add_systems(Update, (
+            game_history,
+            game_action,
             button_system
-        ).chain().run_if(in_state(GameState::Game)))
+        ).chain().run_if(in_state(GameState::Game).and(staying_in_game)))
         .add_systems(OnExit(GameState::Game), despawn_screen::<OnGameScreen>)
         .insert_resource(KeyboardTimer(Timer::from_seconds(0.1, TimerMode::Repeating)))
         .insert_resource(Level(1))
-        .insert_resource(LevelConfig::new(1, "assets/levels/1.json"));
+        .init_resource::<MoveHistory>()
+        .init_resource::<StatusLine>()
+        .init_resource::<LevelConfig>();
 }
 
 #[derive(Component)]
@@ -26,26 +34,64 @@ struct OnGameScreen;
 #[derive(Resource)]
 struct KeyboardTimer(Timer);
 
+// Short message shown under the level, e.g. why the last move was rejected
+#[derive(Resource, Default)]
+struct StatusLine(String);
+
+// The level being played and what goes with it, all reset when a level starts
+#[derive(SystemParam)]
+struct PlayState<'w> {
+    level_config: ResMut<'w, LevelConfig>,
+    history: ResMut<'w, MoveHistory>,
+    status: ResMut<'w, StatusLine>,
+}
+
 const UP: (i32, i32) = (-1, 0);
 const DOWN: (i32, i32) = (1, 0);
 const LEFT: (i32, i32) = (0, -1);
 const RIGHT: (i32, i32) = (0, 1);
 const STAY: (i32, i32) = (0, 0);
 
+// Paraboxes are drawn inside the cells they sit in, this many levels deep. Deeper ones only
+// show their number, so boxes that contain themselves do not draw forever.
+const MAX_RENDER_DEPTH: usize = 3;
+const CELL_FONT_SIZE: f32 = 40.0;
+const BOX_COLORS: [Srgba; 8] = [
+    css::DARK_RED, css::GREEN, css::DARK_BLUE, css::MAGENTA, css::ORANGE, css::PURPLE,
+    css::PINK, css::LIGHT_GRAY
+];
+// Empty targets that need one particular square show a faded symbol of it
+const TARGET_HINT_COLOR: Color = Color::srgba(0.9, 0.9, 0.9, 0.4);
+
+// Whether the game screen stays up this frame; a level that fails to load switches away
+// before it is ever played
+fn staying_in_game(next_state: Res<NextState<GameState>>) -> bool {
+    matches!(*next_state, NextState::Unchanged)
+}
+
 fn game_setup(
     commands: Commands,
     level_settings: Res<Level>,
-    mut level_config: ResMut<LevelConfig>,
+    mut play: PlayState,
+    mut game_state: ResMut<NextState<GameState>>,
+    mut load_error: ResMut<LoadErrorMessage>,
     asset_server: Res<AssetServer>,
 ) {
     //println!("Setting up game screen");
-    level_config.load(level_settings.0);
-    render_game(commands, level_config, asset_server);
+    if let Err(error) = play.level_config.load(level_settings.0) {
+        load_error.0 = error.to_string();
+        game_state.set(GameState::LoadError);
+        return;
+    }
+    play.history.clear();
+    play.status.0.clear();
+    render_game(commands, play.level_config, &play.status.0, asset_server);
 }
 
 fn render_game(
     mut commands: Commands,
     level_config: ResMut<LevelConfig>,
+    status: &str,
     _asset_server: Res<AssetServer>,
 ) {
     //println!("Setting up game screen");
@@ -64,41 +110,133 @@ fn render_game(
         ))
         .with_children(|parent| {
             let parabox_num = level_config.paraboxes.len();
-            let colors = vec![
-                css::DARK_RED, css::GREEN, css::DARK_BLUE, css::MAGENTA, css::ORANGE, css::PURPLE,
-                css::PINK, css::LIGHT_GRAY
-            ];
-            for (id, parabox) in level_config.paraboxes.iter().enumerate() {
+            for id in 0..parabox_num {
                 parent.spawn((
                     Node {
                         width: Val::Percent(100.0 / parabox_num as f32),
                         height: Val::Percent(100.0),
+                        flex_direction: FlexDirection::Column,
                         align_items: AlignItems::Center,
                         justify_content: JustifyContent::Center,
+                        padding: UiRect::all(Val::Px(10.0)),
                         ..default()
                     },
-                    BackgroundColor(colors[id].into()),
+                    BackgroundColor(BOX_COLORS[id % BOX_COLORS.len()].into()),
                 ))
                 .with_children(|parent| {
                     parent.spawn((
-                        Text::new(format!("{:?}", id + 1)),
+                        Text::new(panel_title(&level_config, id)),
                         TextFont {
                             font_size: 60.0,
                             ..default()
                         },
                         TextColor(TEXT_COLOR.into())
                     ));
-                    parent.spawn((
-                        Text::new(format!("{:?}", parabox)),
-                        TextFont {
-                            font_size: 60.0,
-                            ..default()
-                        },
-                        TextColor(TEXT_COLOR.into())),
-                    );
+                    spawn_parabox_grid(parent, &level_config, id, CELL_FONT_SIZE, 0, false);
                 });
             }
         });
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(20.0),
+            left: Val::Px(20.0),
+            ..default()
+        },
+        Text::new(status),
+        TextFont {
+            font_size: 30.0,
+            ..default()
+        },
+        TextColor(TEXT_COLOR.into()),
+        OnGameScreen,
+    ));
+}
+
+// The number of parabox `id`, followed by whatever sets it apart from a plain box.
+fn panel_title(level_config: &LevelConfig, id: usize) -> String {
+    let parabox = &level_config.paraboxes[id];
+    let mut title = format!("{:?}", id + 1);
+    if parabox.is_player() {
+        title.push_str(" player");
+    }
+    if parabox.flipped() {
+        title.push_str(" flipped");
+    }
+    if parabox.locked() {
+        title.push_str(" locked");
+    }
+    title
+}
+
+// Draw parabox `id` as a grid of its cells, with the paraboxes inside it drawn in their cells
+// at a smaller scale until `MAX_RENDER_DEPTH`. A `mirrored` grid is drawn right to left, as a
+// flipped box looks from outside.
+fn spawn_parabox_grid(parent: &mut ChildSpawnerCommands, level_config: &LevelConfig, id: usize, font_size: f32, depth: usize, mirrored: bool) {
+    let parabox = &level_config.paraboxes[id];
+    let (rows, cols) = parabox.size();
+    parent.spawn((
+        Node {
+            display: Display::Grid,
+            width: Val::Percent(100.0),
+            aspect_ratio: Some(cols as f32 / rows as f32),
+            grid_template_rows: RepeatedGridTrack::minmax(rows as u16, MinTrackSizingFunction::Px(0.0), MaxTrackSizingFunction::Fraction(1.0)),
+            grid_template_columns: RepeatedGridTrack::minmax(cols as u16, MinTrackSizingFunction::Px(0.0), MaxTrackSizingFunction::Fraction(1.0)),
+            overflow: Overflow::clip(),
+            ..default()
+        },
+        BackgroundColor(BOX_COLORS[id % BOX_COLORS.len()].into()),
+    ))
+    .with_children(|parent| {
+        for x in 0..rows {
+            for column in 0..cols {
+                let y = if mirrored { cols - 1 - column } else { column };
+                let mut cell = parent.spawn(Node {
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    overflow: Overflow::clip(),
+                    ..default()
+                });
+                match parabox.find_at(x, y) {
+                    Some(Square::Parabox(inner) | Square::Clone(inner) | Square::Infinity(inner)) if depth < MAX_RENDER_DEPTH => {
+                        let (inner_rows, inner_cols) = level_config.paraboxes[*inner as usize].size();
+                        let inner_font_size = font_size / inner_rows.max(inner_cols) as f32;
+                        let inner_mirrored = mirrored != level_config.paraboxes[*inner as usize].flipped();
+                        let inner = *inner as usize;
+                        cell.with_children(|parent| {
+                            spawn_parabox_grid(parent, level_config, inner, inner_font_size, depth + 1, inner_mirrored);
+                        });
+                    }
+                    _ => {
+                        let (glyph, color) = match parabox.typed_target_at((x, y)) {
+                            Some(target) if parabox.find_at(x, y).is_none() => (target_hint(target), TARGET_HINT_COLOR),
+                            _ => (parabox.glyph_at((x, y)), TEXT_COLOR),
+                        };
+                        cell.with_children(|parent| {
+                            parent.spawn((
+                                Text::new(glyph),
+                                TextFont {
+                                    font_size,
+                                    ..default()
+                                },
+                                TextColor(color),
+                            ));
+                        });
+                    }
+                }
+            }
+        }
+    });
+}
+
+// What an empty target that needs `target` shows: the symbol of the square it needs, with the
+// player's number for a particular player.
+fn target_hint(target: Target) -> String {
+    match target {
+        Target::Block => "b".to_string(),
+        Target::Parabox { id } => (id + 1).to_string(),
+        Target::Player { number } => format!("p{}", number + 1),
+    }
 }
 
 fn game_action(
@@ -110,8 +248,9 @@ fn game_action(
     asset_server: Res<AssetServer>,
     mut game_state: ResMut<NextState<GameState>>,
     mut menu_state: ResMut<NextState<menu::MenuState>>,
-    mut level_config: ResMut<LevelConfig>,
+    play: PlayState,
 ) {
+    let PlayState { mut level_config, mut history, mut status } = play;
     if timer.0.tick(time.delta()).just_finished() {
         let movement = if keyboard_input.pressed(KeyCode::ArrowLeft) || keyboard_input.pressed(KeyCode::KeyA) {
             //println!("Move left");
@@ -134,15 +273,38 @@ fn game_action(
             // No movement
             STAY
         };
-        if movement != STAY {
-            let success = level_config.shift(None, None, movement);
-            let win = success && level_config.check_win();
-            // If there was a movement, we can despawn the current game screen
+        let mut win = false;
+        status.0.clear();
+        let changed = if movement != STAY {
+            let before = level_config.clone();
+            // Holding Shift pulls the square behind the player along, in levels that allow it
+            let result = if keyboard_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+                level_config.pull(movement)
+            } else {
+                level_config.shift(movement)
+            };
+            match result {
+                Ok(report) => {
+                    info!("{}", report);
+                    history.record(before);
+                    win = level_config.check_win();
+                    if !win && level_config.out_of_moves() {
+                        status.0 = "Out of moves, press Z to undo".to_string();
+                    }
+                }
+                Err(reason) => status.0 = reason.to_string(),
+            }
+            true
+        } else {
+            false
+        };
+        if changed {
+            // If the level changed, we can despawn the current game screen
             for entity in &query {
                 commands.entity(entity).despawn();
             }
             // And set up the new game screen
-            render_game(commands, level_config, asset_server);
+            render_game(commands, level_config, &status.0, asset_server);
             if win {
                 // If the player won, we transition to the win state
                 game_state.set(GameState::Win);
@@ -151,6 +313,33 @@ fn game_action(
     }
 }
 
+fn game_history(
+    mut commands: Commands,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    query: Query<Entity, With<OnGameScreen>>,
+    asset_server: Res<AssetServer>,
+    mut level_config: ResMut<LevelConfig>,
+    mut history: ResMut<MoveHistory>,
+    mut status: ResMut<StatusLine>,
+) {
+    let changed = if keyboard_input.just_pressed(KeyCode::KeyZ) {
+        // Undo the last move
+        history.undo(&mut level_config)
+    } else if keyboard_input.just_pressed(KeyCode::KeyY) {
+        // Redo the last undone move
+        history.redo(&mut level_config)
+    } else {
+        false
+    };
+    if changed {
+        status.0.clear();
+        for entity in &query {
+            commands.entity(entity).despawn();
+        }
+        render_game(commands, level_config, &status.0, asset_server);
+    }
+}
+
 const GAME_NORMAL_BUTTON: Color = Color::srgb(0.5, 0.5, 0.5); // Normal state: gray
 const GAME_HOVERED_BUTTON: Color = Color::srgb(0.6, 0.6, 0.6); // Hovered state: slightly lighter gray
 const GAME_HOVERED_PRESSED_BUTTON: Color = Color::srgb(0.4, 0.6, 0.4); // Hovered and pressed state: greenish gray
@@ -0,0 +1,113 @@
+use bevy::prelude::*;
+use bevy::color::palettes::*;
+
+use crate::{ GameState, despawn_screen, TEXT_COLOR };
+use crate::plugins::menu::{ MenuButtonAction, menu_action, button_system };
+
+pub fn load_error_plugin(app: &mut App) {
+    app
+        .init_resource::<LoadErrorMessage>()
+        .add_systems(OnEnter(GameState::LoadError), load_error_setup)
+        .add_systems(OnExit(GameState::LoadError), despawn_screen::<OnLoadErrorScreen>)
+        .add_systems(Update, (menu_action, button_system).run_if(in_state(GameState::LoadError)));
+}
+
+// Why the last level failed to load, set by the game screen before switching here
+#[derive(Resource, Default)]
+pub struct LoadErrorMessage(pub String);
+
+#[derive(Component)]
+struct OnLoadErrorScreen;
+
+fn load_error_setup(
+    mut commands: Commands,
+    message: Res<LoadErrorMessage>,
+    _asset_server: Res<AssetServer>,
+) {
+    commands
+        .spawn((
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            BackgroundColor(css::DARK_RED.into()),
+            OnLoadErrorScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(
+                    Node {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        margin: UiRect::all(Val::Px(50.0)),
+                        ..default()
+                    },
+                )
+                .with_children(|parent| {
+                    parent.spawn((
+                        Text::new("Failed to load level"),
+                        TextFont {
+                            font_size: 60.0,
+                            ..default()
+                        },
+                        TextColor(TEXT_COLOR),
+                    ));
+                    // Display what went wrong
+                    parent.spawn((
+                        Text::new(message.0.clone()),
+                        TextFont {
+                            font_size: 30.0,
+                            ..default()
+                        },
+                        TextColor(TEXT_COLOR),
+                    ));
+                    parent
+                        .spawn((
+                            Button,
+                            Node {
+                                margin: UiRect::all(Val::Px(20.0)),
+                                width: Val::Px(350.0),
+                                height: Val::Px(65.0),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            MenuButtonAction::SelectLevel,
+                        ))
+                        .with_children(|parent| {
+                            parent.spawn((Text::new("Levels"),
+                                TextFont {
+                                    font_size: 40.0,
+                                    ..default()
+                                },  
+                                TextColor(TEXT_COLOR),
+                            ));
+                        });
+                    parent
+                        .spawn((
+                            Button,
+                            Node {
+                                margin: UiRect::all(Val::Px(20.0)),
+                                width: Val::Px(350.0),
+                                height: Val::Px(65.0),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            MenuButtonAction::BackToMainMenu,
+                        ))
+                        .with_children(|parent| {
+                            parent.spawn((Text::new("Main Menu"),
+                                TextFont {
+                                    font_size: 40.0,
+                                    ..default()
+                                },  
+                                TextColor(TEXT_COLOR),
+                            ));
+                        });
+                });
+        });
+}
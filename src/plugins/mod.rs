@@ -1,3 +1,4 @@
 pub mod menu;
 pub mod game;
-pub mod win;
\ No newline at end of file
+pub mod win;
+pub mod load_error;
\ No newline at end of file